@@ -105,6 +105,7 @@ use jj_lib::repo_path::UiPathParseError;
 use jj_lib::revset;
 use jj_lib::revset::ResolvedRevsetExpression;
 use jj_lib::revset::RevsetAliasesMap;
+use jj_lib::revset::RevsetCustomFunction;
 use jj_lib::revset::RevsetDiagnostics;
 use jj_lib::revset::RevsetExpression;
 use jj_lib::revset::RevsetExtensions;
@@ -3834,6 +3835,7 @@ pub struct CliRunner<'a> {
     working_copy_factories: WorkingCopyFactories,
     workspace_loader_factory: Box<dyn WorkspaceLoaderFactory>,
     revset_extensions: RevsetExtensions,
+    revset_function_factories: Vec<(&'static str, RevsetFunctionFactory<'a>)>,
     commit_template_extensions: Vec<Arc<dyn CommitTemplateLanguageExtension>>,
     operation_template_extensions: Vec<Arc<dyn OperationTemplateLanguageExtension>>,
     dispatch_fn: CliDispatchFn<'a>,
@@ -3844,6 +3846,9 @@ pub struct CliRunner<'a> {
 pub type CliDispatchFn<'a> =
     Box<dyn FnOnce(&mut Ui, &CommandHelper) -> Result<(), CommandError> + 'a>;
 
+/// Builds a revset function from the settings once they're loaded.
+pub type RevsetFunctionFactory<'a> = Box<dyn FnOnce(&UserSettings) -> RevsetCustomFunction + 'a>;
+
 type CliDispatchHookFn<'a> =
     Box<dyn FnOnce(&mut Ui, &CommandHelper, CliDispatchFn<'a>) -> Result<(), CommandError> + 'a>;
 
@@ -3865,6 +3870,7 @@ impl<'a> CliRunner<'a> {
             working_copy_factories: default_working_copy_factories(),
             workspace_loader_factory: Box::new(DefaultWorkspaceLoaderFactory),
             revset_extensions: Default::default(),
+            revset_function_factories: crate::commands::default_revset_function_factories(),
             commit_template_extensions: vec![],
             operation_template_extensions: vec![],
             dispatch_fn: Box::new(crate::commands::run_command),
@@ -3938,15 +3944,34 @@ impl<'a> CliRunner<'a> {
         self
     }
 
+    /// Registers a revset function. A default function of the same name, such
+    /// as `gerrit_uploaded()`, is replaced.
     pub fn add_revset_function_extension(
         mut self,
         name: &'static str,
         func: RevsetFunction,
     ) -> Self {
+        self.revset_function_factories
+            .retain(|&(existing, _)| existing != name);
         self.revset_extensions.add_custom_function(name, func);
         self
     }
 
+    /// Registers a revset function which is built from the settings once
+    /// they're loaded. A default function of the same name, such as
+    /// `gerrit_uploaded()`, is replaced.
+    pub fn add_revset_function_factory(
+        mut self,
+        name: &'static str,
+        factory: impl FnOnce(&UserSettings) -> RevsetCustomFunction + 'a,
+    ) -> Self {
+        self.revset_function_factories
+            .retain(|&(existing, _)| existing != name);
+        self.revset_function_factories
+            .push((name, Box::new(factory)));
+        self
+    }
+
     pub fn add_commit_template_extension(
         mut self,
         commit_template_extension: Box<dyn CommitTemplateLanguageExtension>,
@@ -4124,6 +4149,10 @@ impl<'a> CliRunner<'a> {
         }
 
         let settings = UserSettings::from_config(config)?;
+        let mut revset_extensions = self.revset_extensions;
+        for (name, factory) in self.revset_function_factories {
+            revset_extensions.add_custom_closure(name, factory(&settings));
+        }
         let command_helper_data = CommandHelperData {
             app: self.app,
             cwd,
@@ -4134,7 +4163,7 @@ impl<'a> CliRunner<'a> {
             config_migrations: self.config_migrations,
             raw_config,
             settings,
-            revset_extensions: self.revset_extensions.into(),
+            revset_extensions: revset_extensions.into(),
             commit_template_extensions: self.commit_template_extensions,
             operation_template_extensions: self.operation_template_extensions,
             maybe_workspace_loader,
@@ -579,6 +579,11 @@ jj currently does not support partial clones. To use jj with this repository, tr
                     "Run `jj git remote rename` to give a different name.",
                 ),
                 GitFetchError::Subprocess(_) => user_error(err),
+                GitFetchError::ReadFetchedRefs(_)
+                | GitFetchError::RemoveFetchedRefs(_)
+                | GitFetchError::ImportFetchedCommits(_)
+                | GitFetchError::UnexpectedFailingRefspec(_) => internal_error(err),
+                GitFetchError::UnexpectedBackend(_) => user_error(err),
             }
         }
     }
@@ -773,6 +778,53 @@ impl From<SecureConfigError> for CommandError {
     }
 }
 
+#[cfg(feature = "gerrit")]
+impl From<crate::commands::gerrit::UploadError> for CommandError {
+    fn from(err: crate::commands::gerrit::UploadError) -> Self {
+        use crate::commands::gerrit::UploadError;
+        match err {
+            UploadError::InvalidChangeIds { .. } => user_error_with_hint(
+                err,
+                "Fix the footers, or set `gerrit.on-invalid-change-id = \"fix\"` to replace them",
+            ),
+            UploadError::RebaseConflict { .. } => user_error_with_hint(
+                err,
+                "Rebase the revisions locally with `jj rebase` and resolve the conflicts, then \
+                 upload again",
+            ),
+            UploadError::Connect(_) => user_error_with_hint(
+                err,
+                "Check your network connection and proxy settings (`gerrit.proxy`, Git's \
+                 `http.proxy`, or the `HTTPS_PROXY` and `ALL_PROXY` environment variables)",
+            ),
+            UploadError::Rejected {
+                ref uploaded_branches,
+            } => {
+                let hint = (!uploaded_branches.is_empty()).then(|| {
+                    format!(
+                        "Changes were already uploaded to {}",
+                        uploaded_branches
+                            .iter()
+                            .map(|branch| format!("'{branch}'"))
+                            .join(", ")
+                    )
+                });
+                let mut cmd_err = user_error(err);
+                cmd_err.extend_hints(hint);
+                cmd_err
+            }
+            UploadError::MultipleChangeIds { .. }
+            | UploadError::Subprocess(_)
+            | UploadError::Push(_)
+            | UploadError::FailedHeads { .. } => user_error(err),
+            UploadError::Backend(err) => err.into(),
+            UploadError::RevsetEvaluation(err) => err.into(),
+            UploadError::DiffRender(err) => err.into(),
+            UploadError::Io(err) => err.into(),
+        }
+    }
+}
+
 fn find_source_parse_error_hint(err: &dyn error::Error) -> Option<String> {
     let source = err.source()?;
     if let Some(source) = source.downcast_ref() {
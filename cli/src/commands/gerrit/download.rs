@@ -0,0 +1,90 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::io::Write as _;
+
+use jj_lib::git::GitSubprocessOptions;
+use jj_lib::repo::Repo as _;
+
+use super::ChangeArg;
+use super::fetch_patchsets;
+use super::parse_change_arg;
+use crate::cli_util::CommandHelper;
+use crate::command_error::CommandError;
+use crate::gerrit_util::Resolved;
+use crate::gerrit_util::calculate_remote;
+use crate::ui::Ui;
+
+/// Download a change from Gerrit for local review
+///
+/// Fetches a patchset of a Gerrit change and checks it out, like `git review
+/// -d` does: a new working-copy commit is created on top of the downloaded
+/// revision. Unless a patchset is specified, the latest one is downloaded.
+#[derive(clap::Args, Clone, Debug)]
+pub struct DownloadArgs {
+    /// The number of the change to download, optionally followed by
+    /// `/<patchset>`
+    #[arg(value_name = "CHANGE[/PATCHSET]", value_parser = parse_change_arg)]
+    change: ChangeArg,
+
+    /// The Gerrit remote to fetch from
+    ///
+    /// Can be configured with the `gerrit.default-remote` repository option as
+    /// well.
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+pub fn cmd_gerrit_download(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DownloadArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    let Resolved { value: remote, .. } =
+        calculate_remote(repo.store(), command.settings(), args.remote.as_deref())?;
+    let subprocess_options = GitSubprocessOptions::from_settings(command.settings())?;
+    let [(patchset, commit)]: [_; 1] = fetch_patchsets(
+        ui,
+        repo.as_ref(),
+        subprocess_options,
+        &remote,
+        &[args.change],
+    )?
+    .try_into()
+    .unwrap();
+
+    let mut tx = workspace_command.start_transaction();
+    tx.repo_mut().add_head(&commit)?;
+    if let Some(mut formatter) = ui.status_formatter() {
+        write!(
+            formatter,
+            "Downloaded patchset {patchset} of change {} as ",
+            args.change.number
+        )?;
+        tx.write_commit_summary(formatter.as_mut(), &commit)?;
+        writeln!(formatter)?;
+    }
+    tx.check_out(&commit)?;
+    tx.finish(
+        ui,
+        format!(
+            "download patchset {patchset} of Gerrit change {}",
+            args.change.number
+        ),
+    )?;
+    Ok(())
+}
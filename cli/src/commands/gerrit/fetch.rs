@@ -0,0 +1,100 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::io::Write as _;
+
+use itertools::Itertools as _;
+use jj_lib::git::GitSubprocessOptions;
+use jj_lib::repo::Repo as _;
+
+use super::ChangeArg;
+use super::fetch_patchsets;
+use super::parse_change_arg;
+use crate::cli_util::CommandHelper;
+use crate::command_error::CommandError;
+use crate::gerrit_util::Resolved;
+use crate::gerrit_util::calculate_remote;
+use crate::ui::Ui;
+
+/// Fetch changes from Gerrit without checking them out
+///
+/// The latest patchset of each change, or the requested one, is fetched in a
+/// single `git fetch`, and added to the repo as an anonymous revision. Unlike
+/// `jj gerrit download`, the working copy is left alone.
+#[derive(clap::Args, Clone, Debug)]
+pub struct FetchArgs {
+    /// The number of a change to fetch, optionally followed by `/<patchset>`
+    ///
+    /// Can be repeated to fetch several changes at once.
+    #[arg(
+        long = "change",
+        short,
+        required = true,
+        value_name = "CHANGE[/PATCHSET]",
+        value_parser = parse_change_arg
+    )]
+    changes: Vec<ChangeArg>,
+
+    /// The Gerrit remote to fetch from
+    ///
+    /// Can be configured with the `gerrit.default-remote` repository option as
+    /// well.
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+pub fn cmd_gerrit_fetch(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &FetchArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    let Resolved { value: remote, .. } =
+        calculate_remote(repo.store(), command.settings(), args.remote.as_deref())?;
+    let subprocess_options = GitSubprocessOptions::from_settings(command.settings())?;
+    let changes = args.changes.iter().copied().unique().collect_vec();
+    let patchsets = fetch_patchsets(ui, repo.as_ref(), subprocess_options, &remote, &changes)?;
+
+    // Revisions shared by several changes, such as the ancestors of a stack,
+    // are the same commits, so they're only added once.
+    let mut tx = workspace_command.start_transaction();
+    for (_, commit) in &patchsets {
+        tx.repo_mut().add_head(commit)?;
+    }
+    if let Some(mut formatter) = ui.status_formatter() {
+        for (change, (patchset, commit)) in changes.iter().zip(&patchsets) {
+            write!(
+                formatter,
+                "Fetched patchset {patchset} of change {} as ",
+                change.number
+            )?;
+            tx.write_commit_summary(formatter.as_mut(), commit)?;
+            writeln!(formatter)?;
+        }
+    }
+    tx.finish(
+        ui,
+        format!(
+            "fetch Gerrit changes {}",
+            changes
+                .iter()
+                .map(|change| change.number)
+                .unique()
+                .join(", ")
+        ),
+    )?;
+    Ok(())
+}
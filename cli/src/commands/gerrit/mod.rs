@@ -12,19 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt;
 use std::fmt::Debug;
 
 use clap::Subcommand;
+use itertools::Itertools as _;
+use jj_lib::commit::Commit;
+use jj_lib::git;
+use jj_lib::git::GitSubprocessOptions;
+use jj_lib::ref_name::RemoteName;
+use jj_lib::repo::Repo;
 
+pub use self::upload::InvalidChangeIdPolicy;
+pub use self::upload::UploadError;
+pub use self::upload::UploadOptions;
+pub use self::upload::UploadReport;
+pub use self::upload::UploadedHead;
+pub use self::upload::upload_commits;
 use crate::cli_util::CommandHelper;
 use crate::command_error::CommandError;
+use crate::command_error::user_error_with_hint;
 use crate::commands::gerrit;
+pub use crate::gerrit_util::ChangeIdSource;
+use crate::gerrit_util::is_remote_url;
+use crate::git_util::with_remote_git_callbacks;
 use crate::ui::Ui;
 
 /// Interact with Gerrit Code Review.
 #[derive(Subcommand, Clone, Debug)]
 pub enum GerritCommand {
-    Upload(gerrit::upload::UploadArgs),
+    Download(gerrit::download::DownloadArgs),
+    Fetch(gerrit::fetch::FetchArgs),
+    Status(gerrit::status::StatusArgs),
+    Upload(Box<gerrit::upload::UploadArgs>),
 }
 
 pub fn cmd_gerrit(
@@ -33,8 +53,147 @@ pub fn cmd_gerrit(
     subcommand: &GerritCommand,
 ) -> Result<(), CommandError> {
     match subcommand {
+        GerritCommand::Download(args) => gerrit::download::cmd_gerrit_download(ui, command, args),
+        GerritCommand::Fetch(args) => gerrit::fetch::cmd_gerrit_fetch(ui, command, args),
+        GerritCommand::Status(args) => gerrit::status::cmd_gerrit_status(ui, command, args),
         GerritCommand::Upload(review) => gerrit::upload::cmd_gerrit_upload(ui, command, review),
     }
 }
 
+/// A Gerrit change number along with an optional patchset number.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct ChangeArg {
+    number: u64,
+    patchset: Option<u64>,
+}
+
+impl fmt::Display for ChangeArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.patchset {
+            Some(patchset) => write!(f, "{}/{patchset}", self.number),
+            None => write!(f, "{}", self.number),
+        }
+    }
+}
+
+impl ChangeArg {
+    /// Returns the ref pattern matching the change's patchsets on Gerrit, e.g.
+    /// `refs/changes/45/12345/*`.
+    fn ref_pattern(&self) -> String {
+        let patchset = match self.patchset {
+            Some(patchset) => patchset.to_string(),
+            None => "*".to_owned(),
+        };
+        format!(
+            "refs/changes/{:02}/{}/{patchset}",
+            self.number % 100,
+            self.number
+        )
+    }
+}
+
+fn parse_change_arg(arg: &str) -> Result<ChangeArg, String> {
+    let (number, patchset) = match arg.split_once('/') {
+        Some((number, patchset)) => (number, Some(patchset)),
+        None => (arg, None),
+    };
+    let parse_number = |value: &str| value.parse::<u64>().ok().filter(|&n| n > 0);
+    let number = parse_number(number);
+    let patchset = patchset.map(parse_number);
+    match (number, patchset) {
+        (Some(number), None) => Ok(ChangeArg {
+            number,
+            patchset: None,
+        }),
+        (Some(number), Some(Some(patchset))) => Ok(ChangeArg {
+            number,
+            patchset: Some(patchset),
+        }),
+        _ => Err(format!("Expected CHANGE or CHANGE/PATCHSET, got '{arg}'")),
+    }
+}
+
+/// Returns the change and patchset numbers of a
+/// `refs/changes/NN/CHANGE/PATCHSET` ref. Other refs of the change, such as its
+/// `meta` ref, are ignored.
+fn parse_patchset_ref(ref_name: &str) -> Option<(u64, u64)> {
+    let rest = ref_name.strip_prefix("refs/changes/")?;
+    let (_, rest) = rest.split_once('/')?;
+    let (number, patchset) = rest.split_once('/')?;
+    Some((number.parse().ok()?, patchset.parse().ok()?))
+}
+
+/// Fetches the requested patchsets of the `changes` from the Gerrit remote in a
+/// single `git fetch`. Returns the patchset number and commit of each change.
+fn fetch_patchsets(
+    ui: &Ui,
+    repo: &dyn Repo,
+    subprocess_options: GitSubprocessOptions,
+    remote: &str,
+    changes: &[ChangeArg],
+) -> Result<Vec<(u64, Commit)>, CommandError> {
+    // Without a patchset, all of them are fetched to find the latest one. They
+    // typically share most of their objects, so this costs little extra.
+    let ref_patterns = changes.iter().map(ChangeArg::ref_pattern).collect_vec();
+    let ref_patterns = ref_patterns.iter().map(String::as_str).collect_vec();
+    let fetched_refs = with_remote_git_callbacks(ui, |callbacks| {
+        if is_remote_url(remote) {
+            git::fetch_refs_from_url(repo, subprocess_options, remote, &ref_patterns, callbacks)
+        } else {
+            git::fetch_refs(
+                repo,
+                subprocess_options,
+                RemoteName::new(remote),
+                &ref_patterns,
+                callbacks,
+            )
+        }
+    })?;
+
+    let mut patchsets = vec![];
+    let mut missing_changes = vec![];
+    for change in changes {
+        let latest = fetched_refs
+            .iter()
+            .filter_map(|(name, id)| {
+                let (number, patchset) = parse_patchset_ref(name)?;
+                let is_match = number == change.number
+                    && change
+                        .patchset
+                        .is_none_or(|requested| requested == patchset);
+                is_match.then_some((patchset, id))
+            })
+            .max_by_key(|(patchset, _)| *patchset);
+        match latest {
+            Some((patchset, id)) => patchsets.push((patchset, repo.store().get_commit(id)?)),
+            None => missing_changes.push(change),
+        }
+    }
+    let message = match missing_changes.as_slice() {
+        [] => return Ok(patchsets),
+        [
+            ChangeArg {
+                number,
+                patchset: Some(patchset),
+            },
+        ] => format!("Patchset {patchset} of change {number} was not found on remote '{remote}'"),
+        [ChangeArg { number, .. }] => {
+            format!("Change {number} was not found on remote '{remote}'")
+        }
+        _ => format!(
+            "Changes {} were not found on remote '{remote}'",
+            missing_changes.iter().join(", ")
+        ),
+    };
+    let hint = if missing_changes.len() == 1 {
+        "Check the change number, and that you have access to the change"
+    } else {
+        "Check the change numbers, and that you have access to the changes"
+    };
+    Err(user_error_with_hint(message, hint))
+}
+
+mod download;
+mod fetch;
+mod status;
 mod upload;
@@ -0,0 +1,147 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::io::Write as _;
+
+use clap_complete::ArgValueCompleter;
+use itertools::Itertools as _;
+use jj_lib::repo::Repo as _;
+use jj_lib::revset::RevsetIteratorExt as _;
+use jj_lib::trailer::parse_description_trailers;
+
+use crate::cli_util::CommandHelper;
+use crate::cli_util::RevisionArg;
+use crate::cli_util::short_commit_hash;
+use crate::command_error::CommandError;
+use crate::complete;
+use crate::formatter::FormatterExt as _;
+use crate::gerrit_util::ChangeIdSource;
+use crate::gerrit_util::get_change_id_prefix;
+use crate::gerrit_util::is_valid_change_id;
+use crate::gerrit_util::new_gerrit_change_id;
+use crate::ui::Ui;
+
+/// Show which revisions have a Gerrit `Change-Id`
+///
+/// For each revision, prints its commit hash, the state of its `Change-Id`
+/// footer, and the Change-Id that `jj gerrit upload` would upload it with. The
+/// state is one of:
+///
+/// * `valid`: the revision has a well-formed `Change-Id` footer, so Gerrit
+///   knows it if it has been uploaded before
+/// * `missing`: the revision has no `Change-Id` footer, so a new one would be
+///   added on upload. The new Change-Id is only shown if it's derived from the
+///   jj change id. With `gerrit.change-id-from = "commit-tree"`, it changes
+///   whenever the revision is rewritten, so `-` is shown instead.
+/// * `invalid`: the `Change-Id` footer isn't 'I' followed by 40 hexadecimal digits
+/// * `multiple`: the revision has several `Change-Id` footers, which `jj gerrit
+///   upload` refuses to upload
+///
+/// The footer key can be configured with `gerrit.change-id-trailer-key`.
+///
+/// With `--stat`, only the number of revisions in each state is printed, e.g.
+/// to see how many revisions would get a new `Change-Id` on upload.
+#[derive(clap::Args, Clone, Debug)]
+pub struct StatusArgs {
+    /// The revisions to show
+    ///
+    /// Defaults to the mutable ancestors of the working-copy commit,
+    /// `mutable() & ::@`.
+    #[arg(
+        long,
+        short,
+        value_name = "REVSETS",
+        add = ArgValueCompleter::new(complete::revset_expression_mutable),
+    )]
+    revisions: Vec<RevisionArg>,
+    /// Print the number of revisions in each state instead of one line per
+    /// revision
+    #[arg(long)]
+    stat: bool,
+}
+
+/// The states a revision's `Change-Id` footer can be in, in the order `--stat`
+/// prints them.
+const STATES: [&str; 4] = ["valid", "missing", "invalid", "multiple"];
+
+pub fn cmd_gerrit_status(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &StatusArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let settings = command.settings();
+    let key = settings.get_string("gerrit.change-id-trailer-key")?;
+    let change_id_from: ChangeIdSource = settings.get("gerrit.change-id-from")?;
+    let change_id_prefix = get_change_id_prefix(settings)?;
+
+    let expression = if args.revisions.is_empty() {
+        workspace_command.parse_revset(ui, &RevisionArg::from("mutable() & ::@".to_owned()))?
+    } else {
+        workspace_command.parse_union_revsets(ui, &args.revisions)?
+    };
+    let commits: Vec<_> = expression
+        .evaluate()?
+        .iter()
+        .commits(workspace_command.repo().store())
+        .try_collect()?;
+
+    let states = commits.iter().map(|commit| {
+        let trailers = parse_description_trailers(commit.description());
+        let change_ids = trailers
+            .iter()
+            .filter(|trailer| trailer.key == key)
+            .map(|trailer| trailer.value.as_str())
+            .collect_vec();
+        let (state, change_id) = match change_ids.as_slice() {
+            [] => match change_id_from {
+                ChangeIdSource::JjChangeId => (
+                    "missing",
+                    new_gerrit_change_id(commit, change_id_from, &change_id_prefix),
+                ),
+                ChangeIdSource::CommitTree => ("missing", "-".to_owned()),
+            },
+            [change_id] if is_valid_change_id(change_id) => ("valid", change_id.to_string()),
+            [change_id] => ("invalid", change_id.to_string()),
+            _ => ("multiple", change_ids.join(", ")),
+        };
+        (commit, state, change_id)
+    });
+
+    let mut formatter = ui.stdout_formatter();
+    if args.stat {
+        let counts = states.counts_by(|(_, state, _)| state);
+        for state in STATES {
+            let count = counts.get(state).copied().unwrap_or(0);
+            writeln!(formatter, "{state:<8} {count}")?;
+        }
+        return Ok(());
+    }
+    for (commit, state, change_id) in states {
+        write!(
+            formatter.labeled("commit_id"),
+            "{}",
+            short_commit_hash(commit.id())
+        )?;
+        write!(formatter, " ")?;
+        if matches!(state, "invalid" | "multiple") {
+            write!(formatter.labeled("warning"), "{state:<8}")?;
+        } else {
+            write!(formatter, "{state:<8}")?;
+        }
+        writeln!(formatter, " {change_id}")?;
+    }
+    Ok(())
+}
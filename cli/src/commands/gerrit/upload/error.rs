@@ -0,0 +1,55 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io;
+
+use jj_lib::backend::BackendError;
+use jj_lib::git::GitPushError;
+use jj_lib::revset::RevsetEvaluationError;
+use thiserror::Error;
+
+use crate::diff_util::DiffRenderError;
+
+/// Error returned by [`upload_commits()`](super::upload_commits).
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("Multiple {key} footers in revision {revision}")]
+    MultipleChangeIds { key: String, revision: String },
+    #[error("Invalid {key} footers in revisions: {}", revisions.join(", "))]
+    InvalidChangeIds { key: String, revisions: Vec<String> },
+    #[error("Rebasing revision {revision} would create conflicts")]
+    RebaseConflict { revision: String },
+    #[error("Failed to connect to the Gerrit remote")]
+    Connect(#[source] GitPushError),
+    #[error("Internal git error while pushing to gerrit")]
+    Subprocess(#[source] GitPushError),
+    #[error(transparent)]
+    Push(GitPushError),
+    #[error("Failed to push all changes to gerrit")]
+    Rejected {
+        /// The branches changes were already uploaded to, if there are several
+        /// target branches.
+        uploaded_branches: Vec<String>,
+    },
+    #[error("Failed to push {failed} of {total} heads to gerrit")]
+    FailedHeads { failed: usize, total: usize },
+    #[error(transparent)]
+    Backend(#[from] BackendError),
+    #[error(transparent)]
+    RevsetEvaluation(#[from] RevsetEvaluationError),
+    #[error(transparent)]
+    DiffRender(#[from] DiffRenderError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,2887 @@
+// Copyright 2024 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+mod error;
+mod plan;
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error as _;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::io::Write as _;
+use std::iter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use clap_complete::ArgValueCandidates;
+use clap_complete::ArgValueCompleter;
+use indexmap::IndexSet;
+use itertools::Itertools as _;
+use jj_lib::backend::CommitId;
+use jj_lib::commit::Commit;
+use jj_lib::config::ConfigGetResultExt as _;
+use jj_lib::config::ConfigNamePathBuf;
+use jj_lib::copies::CopyRecords;
+use jj_lib::file_util::IoResultExt as _;
+use jj_lib::git;
+use jj_lib::git::GitRefUpdate;
+use jj_lib::git::GitSubprocessOptions;
+use jj_lib::matchers::EverythingMatcher;
+use jj_lib::merge::Diff;
+use jj_lib::object_id::ObjectId as _;
+use jj_lib::ref_name::RefName;
+use jj_lib::ref_name::RemoteName;
+use jj_lib::repo::MutableRepo;
+use jj_lib::repo::Repo;
+use jj_lib::revset::RevsetExpression;
+use jj_lib::revset::RevsetIteratorExt as _;
+use jj_lib::rewrite::CommitRewriter;
+use jj_lib::rewrite::merge_commit_trees;
+use jj_lib::settings::UserSettings;
+use jj_lib::store::Store;
+use jj_lib::str_util::StringPattern;
+use jj_lib::trailer::Trailer;
+use jj_lib::trailer::parse_description_trailers;
+use pollster::FutureExt as _;
+
+pub use self::error::UploadError;
+use self::plan::UploadPlan;
+use self::plan::UploadPlanner;
+use crate::cli_util::CommandHelper;
+use crate::cli_util::RevisionArg;
+use crate::cli_util::WorkspaceCommandHelper;
+use crate::cli_util::WorkspaceCommandTransaction;
+use crate::cli_util::short_change_hash;
+use crate::command_error::CommandError;
+use crate::command_error::internal_error;
+use crate::command_error::print_error_sources;
+use crate::command_error::user_error;
+use crate::command_error::user_error_with_hint;
+use crate::command_error::user_error_with_message;
+use crate::complete;
+use crate::config::CommandNameAndArgs;
+use crate::diff_util::DiffFormat;
+use crate::formatter::Formatter;
+use crate::gerrit_util::ChangeIdSource;
+use crate::gerrit_util::Resolved;
+use crate::gerrit_util::calculate_remote;
+use crate::gerrit_util::config_source;
+use crate::gerrit_util::get_expanded_string;
+use crate::gerrit_util::is_remote_url;
+use crate::gerrit_util::is_valid_change_id;
+use crate::gerrit_util::new_gerrit_change_id;
+use crate::git_util::print_push_stats;
+use crate::git_util::with_remote_git_callbacks_capturing_sideband;
+use crate::ui::Ui;
+
+/// Upload changes to Gerrit for code review, or update existing changes.
+///
+/// Uploading in a set of revisions to Gerrit creates a single "change" for
+/// each revision included in the revset. These changes are then available
+/// for review on your Gerrit instance.
+///
+/// Note: The gerrit commit Id may not match that of your local commit Id,
+/// since we add a `Change-Id` footer to the commit message if one does not
+/// already exist. This ID is based off the jj Change-Id, but is not the same.
+///
+/// If a change already exists for a given revision (i.e. it contains the
+/// same `Change-Id`), this command will update the contents of the existing
+/// change to match.
+///
+/// Note: this command takes 1-or-more revsets arguments, each of which can
+/// resolve to multiple revisions; so you may post trees or ranges of
+/// commits to Gerrit for review all at once.
+#[derive(clap::Args, Clone, Debug)]
+pub struct UploadArgs {
+    /// The revset, selecting which revisions are sent in to Gerrit
+    ///
+    /// This can be any arbitrary set of commits. Note that when you push a
+    /// commit at the head of a stack, all ancestors are pushed too. This means
+    /// that `jj gerrit upload -r foo` is equivalent to `jj gerrit upload -r
+    /// 'mutable()::foo`.
+    ///
+    /// If neither this nor `--stack` is given, `@` is uploaded.
+    #[arg(long, short = 'r')]
+    revisions: Vec<RevisionArg>,
+
+    /// Upload the whole stack of mutable revisions containing `@`
+    ///
+    /// This uploads the descendants of `@` along with their mutable
+    /// ancestors. If this selects more than `gerrit.upload.confirm-threshold`
+    /// revisions (10 if unset), confirmation is requested.
+    #[arg(long, conflicts_with = "revisions")]
+    stack: bool,
+
+    /// Don't snapshot the working copy unless it's uploaded
+    ///
+    /// Snapshotting a large working copy can be slow. With this flag, the
+    /// working copy is only snapshotted if the working-copy commit or one of
+    /// its descendants would be uploaded. Revsets which match on file contents
+    /// see the working-copy commit as of the last snapshot.
+    #[arg(
+        long,
+        conflicts_with_all = ["stack", "record_change_number", "replace_change_id"]
+    )]
+    skip_snapshot: bool,
+
+    /// The location where your changes are intended to land
+    ///
+    /// This should be a branch on the remote. Can be configured with the
+    /// `gerrit.default-remote-branch` repository option.
+    ///
+    /// A glob such as `release/*` uploads the revisions to every matching
+    /// branch on the remote, as of the last fetch. If it matches more than one
+    /// branch, `--confirm` or `--yes` is required.
+    #[arg(
+        long = "remote-branch",
+        short = 'b',
+        add = ArgValueCandidates::new(complete::gerrit_remote_branches),
+    )]
+    remote_branch: Option<String>,
+
+    /// Push each stack to the branch it is based on
+    ///
+    /// The target branch of each stack is inferred from the nearest ancestor
+    /// bookmark on the Gerrit remote. Stacks without such a bookmark are
+    /// pushed to `--remote-branch` or `gerrit.default-remote-branch`.
+    #[arg(long)]
+    auto_branch: bool,
+
+    /// The Gerrit remote to push to
+    ///
+    /// Can be configured with the `gerrit.default-remote` repository option as
+    /// well. This is typically a full SSH URL for your Gerrit instance.
+    ///
+    /// Besides remote names, URLs and aliases rewritten by Git's
+    /// `url.<base>.insteadOf` option are accepted.
+    #[arg(long)]
+    remote: Option<String>,
+
+    /// Rebase the revisions onto this revision before uploading
+    ///
+    /// The roots of the uploaded stack are moved onto the given revision,
+    /// independent of `--remote-branch`. Like the `Change-Id` footers, the
+    /// rebased commits are only uploaded and never recorded locally. If the
+    /// rebase would produce conflicts, the upload is aborted.
+    #[arg(long, value_name = "REVSET")]
+    #[arg(add = ArgValueCompleter::new(complete::revset_expression_all))]
+    rebase_on: Option<RevisionArg>,
+
+    /// Add a trailer to the description of each uploaded revision
+    ///
+    /// Trailers which are already present with the same value are not
+    /// duplicated. Like the `Change-Id` footers, the trailers are only
+    /// uploaded and never recorded locally.
+    #[arg(long = "trailer", value_name = "KEY=VALUE", value_parser = parse_trailer_arg)]
+    trailers: Vec<(String, String)>,
+
+    /// Push the revisions without adding `Change-Id` footers
+    ///
+    /// Unless `--rebase-on` or `--trailer` is given, the pushed commits are the
+    /// local ones. This is useful for pushing to review tools other than Gerrit
+    /// which don't use `Change-Id` footers.
+    #[arg(long)]
+    no_change_id: bool,
+
+    /// Show the diff stat of each stack before pushing it
+    ///
+    /// The stat is relative to the parents of the stack's roots. This is
+    /// always shown with `--dry-run`.
+    #[arg(long)]
+    show_diff_stat: bool,
+
+    /// Show where the remote, branch, topic and reviewers come from
+    ///
+    /// For each setting, this shows whether it was passed on the command line,
+    /// which config it was read from, or how it was determined otherwise.
+    /// Requires `--dry-run`.
+    #[arg(long, requires = "dry_run")]
+    verbose: bool,
+
+    /// Hide messages from the remote unless the push fails
+    ///
+    /// Messages such as banners and hints are hidden. The URLs of the
+    /// uploaded changes are still listed.
+    #[arg(long)]
+    quiet_remote: bool,
+
+    /// Keep pushing the remaining heads if pushing one of them fails
+    ///
+    /// By default, the upload stops at the first head that fails to push. With
+    /// this flag, all heads are attempted, and the heads that were and weren't
+    /// uploaded are listed at the end. The command still fails if any head
+    /// couldn't be pushed.
+    #[arg(long)]
+    keep_going: bool,
+
+    /// Upload empty revisions instead of refusing to
+    ///
+    /// Empty revisions are usually uploaded by mistake, e.g. after squashing
+    /// the working-copy commit into its parent. With this flag, they are
+    /// uploaded with a warning instead.
+    #[arg(long)]
+    allow_empty: bool,
+
+    /// Open the uploaded changes in a web browser
+    ///
+    /// Each change URL reported by Gerrit is opened with the command configured
+    /// in `gerrit.browser`, or the system's default browser.
+    #[arg(long)]
+    open: bool,
+
+    /// Do not actually push the changes to Gerrit
+    #[arg(long = "dry-run", short = 'n')]
+    dry_run: bool,
+
+    /// Write the descriptions that would be uploaded to files in this
+    /// directory, without pushing anything
+    ///
+    /// Each file is named after the short change ID of the revision, and
+    /// contains its description including the `Change-Id` and other added
+    /// trailers.
+    #[arg(long, value_name = "DIR", value_hint = clap::ValueHint::DirPath)]
+    dump_descriptions: Option<PathBuf>,
+
+    /// List the revisions that would be uploaded, without uploading them
+    ///
+    /// Since uploading a revision also uploads its mutable ancestors, this
+    /// may include more revisions than were selected. Each revision is marked
+    /// with whether it already has a `Change-Id` or would get a new one.
+    #[arg(long)]
+    show_upload_set: bool,
+
+    /// Ask for the Change-Id of each revision that needs a new one
+    ///
+    /// The prompt is pre-filled with the Change-Id that would be added
+    /// otherwise. Without a terminal to prompt on, that Change-Id is used.
+    #[arg(long, conflicts_with = "no_change_id")]
+    edit_change_id: bool,
+
+    /// Replace existing `Change-Id` footers with the ones derived from the
+    /// revisions
+    ///
+    /// This is useful to fix revisions that picked up a foreign or malformed
+    /// Change-Id, e.g. by cherry-picking. Like new `Change-Id` footers, the
+    /// replacements are only uploaded and never recorded locally.
+    #[arg(long, conflicts_with = "no_change_id")]
+    update_change_id: bool,
+
+    /// Replace the `Change-Id` trailer `OLD` with `NEW`, without uploading
+    ///
+    /// All revisions that would be uploaded and have a `Change-Id: OLD`
+    /// trailer are rewritten to have `Change-Id: NEW` instead. This is useful
+    /// to fix Change-Ids that turn out to collide with existing changes.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["dry_run", "dump_descriptions", "show_upload_set", "record_change_number"]
+    )]
+    replace_change_id: Option<Vec<String>>,
+
+    /// Ask Gerrit to submit the changes right after uploading them
+    ///
+    /// If all submit requirements are already met, the changes are merged
+    /// into the target branch immediately. This must be enabled with the
+    /// `gerrit.allow-auto-submit` option. Work-in-progress and draft changes
+    /// can't be submitted.
+    #[arg(long, conflicts_with_all = ["wip", "draft"])]
+    submit: bool,
+
+    /// Mark the new patchsets as message-only updates
+    ///
+    /// Sets the patchset message to "Message update", which is useful when
+    /// only the descriptions of the revisions changed since the last upload.
+    #[arg(long)]
+    amend_message: bool,
+
+    /// Set the message of the new patchsets
+    ///
+    /// The message is shown on each uploaded change in Gerrit, and is useful
+    /// to explain what changed since the last upload.
+    #[arg(long, conflicts_with = "amend_message")]
+    message: Option<String>,
+
+    /// Mark the uploaded changes as work in progress
+    ///
+    /// Reviewers aren't notified about work-in-progress changes. This is the
+    /// default if `gerrit.upload-wip` is enabled.
+    #[arg(long, conflicts_with = "ready")]
+    wip: bool,
+
+    /// Mark the uploaded changes as ready for review
+    ///
+    /// This removes the work-in-progress state of existing changes.
+    #[arg(long)]
+    ready: bool,
+
+    /// Upload the changes as drafts
+    ///
+    /// The revisions are pushed to `refs/drafts/<branch>` instead of
+    /// `refs/for/<branch>`, for servers that still support draft changes.
+    #[arg(long, conflicts_with = "ready")]
+    draft: bool,
+
+    /// Mark the uploaded changes as private
+    ///
+    /// Private changes are only visible to their owner and reviewers.
+    #[arg(long, conflicts_with = "remove_private")]
+    private: bool,
+
+    /// Make the uploaded changes visible to everyone again
+    ///
+    /// This only has an effect when re-uploading existing private changes.
+    #[arg(long)]
+    remove_private: bool,
+
+    /// Add a hashtag to the uploaded changes
+    ///
+    /// Can be passed multiple times. If it isn't passed, the hashtags in
+    /// `gerrit.default-hashtags` are added instead.
+    #[arg(long = "hashtag", value_name = "TAG")]
+    hashtags: Vec<String>,
+
+    /// Vote on a label of the uploaded changes, e.g. `Code-Review+2`
+    ///
+    /// Can be passed multiple times to vote on several labels.
+    #[arg(long = "label", value_name = "LABEL", value_parser = parse_label_arg)]
+    labels: Vec<String>,
+
+    /// Group the uploaded changes under this topic
+    ///
+    /// Can be configured with the `gerrit.default-topic` option as well.
+    #[arg(long)]
+    topic: Option<String>,
+
+    /// Prefix the topic with this, e.g. `myteam/`
+    ///
+    /// Applies to the topic from `--topic` as well as to the configured one,
+    /// so that the topics of a team can be found together in Gerrit.
+    #[arg(long, value_name = "PREFIX", value_parser = parse_topic_prefix_arg)]
+    topic_prefix: Option<String>,
+
+    /// Add a reviewer to the uploaded changes
+    ///
+    /// This can be an email address or a group name, and can be passed
+    /// multiple times. If it isn't passed, the reviewers in `gerrit.reviewers`
+    /// are added instead.
+    #[arg(long = "reviewer", value_name = "EMAIL")]
+    reviewers: Vec<String>,
+
+    /// Notify this account about the uploaded changes, without asking it for
+    /// a review
+    ///
+    /// This can be an email address or a group name, and can be passed
+    /// multiple times. If it isn't passed, the accounts in `gerrit.cc` are
+    /// notified instead.
+    #[arg(long, value_name = "EMAIL")]
+    cc: Vec<String>,
+
+    /// Add the reviewers listed in a file, one per line
+    ///
+    /// Each line holds an email address or a group name. Blank lines and
+    /// lines starting with `#` are ignored, and duplicate reviewers are only
+    /// added once.
+    #[arg(long, value_name = "PATH", value_hint = clap::ValueHint::FilePath)]
+    reviewers_file: Option<PathBuf>,
+
+    /// Upload even if descriptions don't pass `gerrit.message-lint`
+    #[arg(long)]
+    no_lint: bool,
+
+    /// Record the Gerrit change numbers in the local revisions
+    ///
+    /// After uploading, a `Gerrit-Change: <number>` trailer is added to the
+    /// description of each uploaded revision that Gerrit reported a change
+    /// number for. Revisions which already have the trailer are left alone.
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "dump_descriptions", "no_change_id"]
+    )]
+    record_change_number: bool,
+
+    /// Always ask for confirmation before uploading
+    ///
+    /// By default, confirmation is only requested when the number of
+    /// revisions to upload exceeds `gerrit.upload.confirm-threshold`, or the
+    /// number of heads exceeds `gerrit.upload.confirm-heads-threshold`.
+    #[arg(long, conflicts_with = "yes")]
+    confirm: bool,
+
+    /// Never ask for confirmation before uploading
+    #[arg(long)]
+    yes: bool,
+}
+
+fn parse_trailer_arg(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("Expected KEY=VALUE, got '{arg}'"))?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == ':') {
+        return Err(format!("Invalid trailer key '{key}'"));
+    }
+    if value.is_empty() || value.contains('\n') {
+        return Err(format!("Invalid trailer value '{value}'"));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Checks that `arg` is a vote like `Code-Review+2` or `Verified-1`.
+fn parse_label_arg(arg: &str) -> Result<String, String> {
+    let is_valid = arg.rfind(['+', '-']).is_some_and(|pos| {
+        let (name, vote) = (&arg[..pos], &arg[pos + 1..]);
+        !name.is_empty()
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+            && vote.len() == 1
+            && vote.chars().all(|c| c.is_ascii_digit())
+    });
+    if !is_valid {
+        return Err(format!("Expected LABEL+N or LABEL-N, got '{arg}'"));
+    }
+    Ok(arg.to_owned())
+}
+
+/// Checks that `arg` is a non-empty topic prefix without whitespace, which
+/// would have to be quoted when searching for the topics in Gerrit.
+fn parse_topic_prefix_arg(arg: &str) -> Result<String, String> {
+    if arg.is_empty() {
+        return Err("The topic prefix must not be empty".to_owned());
+    }
+    if arg.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(format!(
+            "The topic prefix must not contain whitespace, got '{arg}'"
+        ));
+    }
+    Ok(arg.to_owned())
+}
+
+/// Determine what Gerrit ref and remote to use. The logic is:
+///
+/// 1. If the user specifies `--remote-branch branch`, use that
+/// 2. If the user has 'gerrit.remotes.<remote>.default-remote-branch'
+///    configured, use that
+/// 3. If the user has 'gerrit.default-remote-branch' configured, use that
+/// 4. If the repo's `.gerrit.toml` specifies a branch, use that
+/// 5. If the user has 'gerrit.assume-default-branch' configured, use that
+/// 6. Otherwise, bail out
+///
+/// Also returns whether the branch was assumed in case 5, which the user
+/// should be told about.
+fn calculate_push_ref(
+    settings: &UserSettings,
+    remote: &str,
+    remote_branch: Option<String>,
+    repo_defaults: &RepoDefaults,
+) -> Result<(Resolved, bool), CommandError> {
+    // case 1
+    if let Some(remote_branch) = remote_branch {
+        return Ok((Resolved::new(remote_branch, "--remote-branch"), false));
+    }
+
+    // case 2
+    let remote_key = ["gerrit", "remotes", remote, "default-remote-branch"];
+    if let Some(branch) = get_expanded_string(settings, remote_key.as_slice())? {
+        return Ok((
+            Resolved::new(branch, config_source(settings, remote_key.as_slice())),
+            false,
+        ));
+    }
+
+    // case 3
+    if let Some(branch) = get_expanded_string(settings, "gerrit.default-remote-branch")? {
+        return Ok((
+            Resolved::new(
+                branch,
+                config_source(settings, "gerrit.default-remote-branch"),
+            ),
+            false,
+        ));
+    }
+
+    // case 4
+    if let Some(branch) = &repo_defaults.branch {
+        return Ok((
+            Resolved::new(branch.clone(), REPO_DEFAULTS_FILE_NAME),
+            false,
+        ));
+    }
+
+    // case 5
+    if let Some(branch) = get_expanded_string(settings, "gerrit.assume-default-branch")?
+        && !branch.is_empty()
+    {
+        return Ok((
+            Resolved::new(
+                branch,
+                config_source(settings, "gerrit.assume-default-branch"),
+            ),
+            true,
+        ));
+    }
+
+    // case 6
+    Err(user_error(
+        "No target branch specified via --remote-branch, and no 'gerrit.default-remote-branch' \
+         was found",
+    ))
+}
+
+/// Determine the topic to group the uploaded changes under, if any
+///
+/// `--topic` takes precedence over `gerrit.remotes.<remote>.default-topic`,
+/// then `gerrit.default-topic`, then the repo's `.gerrit.toml`. An empty
+/// configured topic disables the ones with lower precedence.
+fn calculate_topic(
+    settings: &UserSettings,
+    remote: &str,
+    topic: Option<&str>,
+    repo_defaults: &RepoDefaults,
+) -> Result<Option<Resolved>, CommandError> {
+    if let Some(topic) = topic {
+        if topic.is_empty() {
+            return Err(user_error("The topic passed to --topic must not be empty"));
+        }
+        return Ok(Some(Resolved::new(topic, "--topic")));
+    }
+    let remote_key = ["gerrit", "remotes", remote, "default-topic"];
+    for key in [remote_key.as_slice(), &["gerrit", "default-topic"]] {
+        if let Some(topic) = get_expanded_string(settings, key)? {
+            return Ok(
+                (!topic.is_empty()).then(|| Resolved::new(topic, config_source(settings, key)))
+            );
+        }
+    }
+    Ok(repo_defaults
+        .topic
+        .as_ref()
+        .map(|topic| Resolved::new(topic, REPO_DEFAULTS_FILE_NAME)))
+}
+
+/// Returns the values passed to `arg`, or the ones listed in the
+/// `gerrit.remotes.<remote>.<name>` or `gerrit.<name>` setting if none were
+/// passed.
+fn resolve_list_values(
+    settings: &UserSettings,
+    remote: &str,
+    values: &[String],
+    arg: &str,
+    name: &str,
+    what: &str,
+) -> Result<Vec<Resolved>, CommandError> {
+    if !values.is_empty() {
+        if values.iter().any(|value| value.is_empty()) {
+            return Err(user_error(format!(
+                "The {what} passed to {arg} must not be empty"
+            )));
+        }
+        return Ok(values
+            .iter()
+            .map(|value| Resolved::new(value, arg))
+            .collect());
+    }
+    let remote_key = ["gerrit", "remotes", remote, name];
+    for key in [remote_key.as_slice(), &["gerrit", name]] {
+        let Some(configured) = settings.get::<Vec<String>>(key).optional()? else {
+            continue;
+        };
+        if configured.iter().any(|value| value.is_empty()) {
+            let key = ConfigNamePathBuf::from_iter(key.iter().copied());
+            return Err(user_error(format!(
+                "`{key}` must not contain empty {what}s"
+            )));
+        }
+        let source = config_source(settings, key);
+        return Ok(configured
+            .into_iter()
+            .map(|value| Resolved::new(value, &source))
+            .collect());
+    }
+    Ok(vec![])
+}
+
+/// Name of the file in the workspace root with project-wide upload defaults.
+const REPO_DEFAULTS_FILE_NAME: &str = ".gerrit.toml";
+
+/// Upload defaults checked into the repository by the project.
+///
+/// These have the lowest precedence: command-line arguments and `gerrit.*`
+/// config, including repo config, override them.
+#[derive(Debug, Default, serde::Deserialize)]
+struct RepoDefaults {
+    /// Target branch, like `gerrit.default-remote-branch`.
+    branch: Option<String>,
+    /// Topic to group the uploaded changes under.
+    topic: Option<String>,
+    /// Reviewers to add to the uploaded changes.
+    #[serde(default)]
+    reviewers: Vec<String>,
+    /// Keys this version doesn't know about, which are ignored so that projects
+    /// can use newer keys without breaking older clients.
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde::de::IgnoredAny>,
+}
+
+/// Reads the `.gerrit.toml` file in the workspace root if the user opted in
+/// with `gerrit.use-repo-defaults`.
+///
+/// The file comes from the repository rather than from the user, so it's
+/// ignored unless they trust it.
+fn load_repo_defaults(
+    ui: &Ui,
+    settings: &UserSettings,
+    workspace_root: &Path,
+) -> Result<RepoDefaults, CommandError> {
+    if !settings.get_bool("gerrit.use-repo-defaults")? {
+        return Ok(RepoDefaults::default());
+    }
+    let path = workspace_root.join(REPO_DEFAULTS_FILE_NAME);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(RepoDefaults::default()),
+        Err(err) => Err(err).context(&path)?,
+    };
+    let defaults: RepoDefaults = toml_edit::de::from_str(&text).map_err(|err| {
+        user_error_with_message(format!("Failed to parse {REPO_DEFAULTS_FILE_NAME}"), err)
+    })?;
+    for key in defaults.unknown.keys() {
+        writeln!(
+            ui.warning_default(),
+            "Ignoring unknown key '{key}' in {REPO_DEFAULTS_FILE_NAME}"
+        )?;
+    }
+    if let Some(branch) = &defaults.branch {
+        check_target_branch(branch).map_err(|err| {
+            err.hinted(format!(
+                "The branch is set by `branch` in {REPO_DEFAULTS_FILE_NAME}"
+            ))
+        })?;
+    }
+    Ok(defaults)
+}
+
+/// Reads the reviewers listed in `path`, one per line. Blank lines and lines
+/// starting with `#` are ignored.
+fn read_reviewers_file(path: &Path) -> Result<Vec<String>, CommandError> {
+    let text = fs::read_to_string(path).context(path)?;
+    let reviewers = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect();
+    Ok(reviewers)
+}
+
+/// Percent-encodes every byte of a push option value except ASCII
+/// alphanumerics.
+///
+/// The value becomes part of the pushed ref name, so this keeps Gerrit from
+/// mistaking characters in it for option separators, and keeps characters like
+/// `~`, `..` or a `.lock` suffix from making the refspec invalid.
+fn encode_push_option_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            encoded.push(byte.into());
+        } else {
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+    encoded
+}
+
+/// Infers the branch a head is based on from the nearest ancestor bookmark on
+/// the Gerrit remote.
+///
+/// Returns `None` if there is no such bookmark, or if it's ambiguous which
+/// one is the nearest.
+fn infer_target_branch(
+    repo: &dyn Repo,
+    remote: &RemoteName,
+    head: &CommitId,
+) -> Result<Option<String>, CommandError> {
+    let mut bookmarks_by_target: HashMap<CommitId, Vec<&RefName>> = HashMap::new();
+    for (name, remote_ref) in repo.view().remote_bookmarks(remote) {
+        for id in remote_ref.target.added_ids() {
+            bookmarks_by_target
+                .entry(id.clone())
+                .or_default()
+                .push(name);
+        }
+    }
+    let bases: Vec<CommitId> = RevsetExpression::commit(head.clone())
+        .ancestors()
+        .intersection(&RevsetExpression::commits(
+            bookmarks_by_target.keys().cloned().collect(),
+        ))
+        .heads()
+        .evaluate(repo)?
+        .iter()
+        .try_collect()?;
+    let [base] = bases.as_slice() else {
+        return Ok(None);
+    };
+    match bookmarks_by_target[base].as_slice() {
+        [name] => Ok(Some(name.as_str().to_owned())),
+        _ => Ok(None),
+    }
+}
+
+/// Returns whether `branch` is a glob pattern rather than a branch name.
+///
+/// Git doesn't allow these characters in ref names, so they can't be part of
+/// a real branch name.
+fn is_branch_pattern(branch: &str) -> bool {
+    branch.contains(['*', '?', '['])
+}
+
+/// Expands the glob `pattern` to the branches on `remote` that match it, as of
+/// the last fetch.
+fn expand_branch_pattern(
+    repo: &dyn Repo,
+    remote: &RemoteName,
+    pattern: &str,
+) -> Result<Vec<String>, CommandError> {
+    let matcher = StringPattern::glob(pattern)
+        .map_err(|err| {
+            user_error_with_message(format!("Invalid --remote-branch pattern '{pattern}'"), err)
+        })?
+        .to_matcher();
+    let branches = repo
+        .view()
+        .remote_bookmarks(remote)
+        .filter(|(name, remote_ref)| remote_ref.is_present() && matcher.is_match(name.as_str()))
+        .map(|(name, _)| name.as_str().to_owned())
+        .collect_vec();
+    if branches.is_empty() {
+        return Err(user_error_with_hint(
+            format!(
+                "No branches on remote '{}' match '{pattern}'",
+                remote.as_str()
+            ),
+            "Run `jj git fetch` to update the list of remote branches",
+        ));
+    }
+    Ok(branches)
+}
+
+/// Moves the `Change-Id` trailer (named `key`) to the end of the trailer
+/// paragraph, which is where Gerrit's `commit-msg` hook puts it.
+fn move_change_id_trailer_last(description: &str, key: &str, change_id: &str) -> String {
+    let description = description.trim_end();
+    let (body, trailer_paragraph) = description.rsplit_once("\n\n").unwrap_or(("", description));
+    let mut new_description = String::with_capacity(description.len() + 1);
+    if !body.is_empty() {
+        new_description.push_str(body);
+        new_description.push_str("\n\n");
+    }
+    for line in trailer_paragraph.lines() {
+        let is_change_id = line
+            .split_once(':')
+            .is_some_and(|(line_key, _)| line_key.trim_end() == key);
+        if !is_change_id {
+            new_description.push_str(line);
+            new_description.push('\n');
+        }
+    }
+    new_description.push_str(key);
+    new_description.push_str(": ");
+    new_description.push_str(change_id);
+    new_description.push('\n');
+    new_description
+}
+
+/// Passes `key = value` to the Git subprocess through the `GIT_CONFIG_*`
+/// environment variables, keeping any config already passed that way.
+fn add_git_config_env(options: &mut GitSubprocessOptions, key: &str, value: &str) {
+    let count_var = OsString::from("GIT_CONFIG_COUNT");
+    let count: usize = options
+        .environment
+        .get(&count_var)
+        .cloned()
+        .or_else(|| env::var_os(&count_var))
+        .and_then(|count| count.to_str()?.parse().ok())
+        .unwrap_or(0);
+    options
+        .environment
+        .insert(format!("GIT_CONFIG_KEY_{count}").into(), key.into());
+    options
+        .environment
+        .insert(format!("GIT_CONFIG_VALUE_{count}").into(), value.into());
+    options
+        .environment
+        .insert(count_var, (count + 1).to_string().into());
+}
+
+/// Returns true if the Git error message indicates that the remote couldn't
+/// be reached.
+fn is_connection_error(message: &str) -> bool {
+    [
+        "unable to access",
+        "Could not resolve",
+        "Failed to connect",
+        "Connection refused",
+        "Connection timed out",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Refuses target branches that would not result in a plain `refs/for/<branch>`
+/// magic ref, such as a branch that is itself already a magic ref, or one that
+/// would smuggle push options into the ref.
+fn check_target_branch(branch: &str) -> Result<(), CommandError> {
+    const MAGIC_PREFIXES: [&str; 3] = ["refs/for/", "refs/drafts/", "refs/publish/"];
+    if branch.is_empty() {
+        return Err(user_error("The target branch must not be empty"));
+    }
+    if MAGIC_PREFIXES
+        .iter()
+        .any(|prefix| branch.starts_with(prefix))
+    {
+        return Err(user_error_with_hint(
+            format!("Target branch '{branch}' is already a Gerrit magic ref"),
+            "Pass the name of the branch to review against, e.g. `main`. Changes are always \
+             uploaded to `refs/for/<branch>`",
+        ));
+    }
+    // Gerrit parses everything after the first `%` as push options, so
+    // `main%submit` would submit the changes.
+    if branch.contains('%') {
+        return Err(user_error_with_hint(
+            format!("Target branch '{branch}' contains '%'"),
+            "Gerrit would read the part after '%' as push options. Use the upload flags, \
+             e.g. `--topic` or `--reviewer`, to set push options",
+        ));
+    }
+    Ok(())
+}
+
+/// Builds the Gerrit "magic" ref to push to, e.g. `refs/for/main%submit`, or
+/// `refs/drafts/main` for drafts.
+///
+/// Gerrit parses everything after the first `%` as a comma-separated list of
+/// push options.
+fn format_push_ref(remote_branch: &str, draft: bool, push_options: &[String]) -> String {
+    let prefix = if draft { "refs/drafts" } else { "refs/for" };
+    let mut remote_ref = format!("{prefix}/{remote_branch}");
+    if !push_options.is_empty() {
+        remote_ref.push('%');
+        remote_ref.push_str(&push_options.join(","));
+    }
+    remote_ref
+}
+
+/// Longest subject line `gerrit.message-lint` accepts, in characters.
+const MAX_SUBJECT_LENGTH: usize = 72;
+
+/// Checks `description` for common review nits, returning a message for each
+/// problem found.
+fn lint_description(description: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let mut lines = description.lines();
+    let subject = lines.next().unwrap_or_default();
+    let subject_length = subject.chars().count();
+    if subject_length > MAX_SUBJECT_LENGTH {
+        problems.push(format!(
+            "subject is {subject_length} characters long, more than {MAX_SUBJECT_LENGTH}"
+        ));
+    }
+    if lines.next().is_some_and(|line| !line.is_empty()) {
+        problems.push("subject is not followed by a blank line".to_owned());
+    }
+    for (index, line) in description.lines().enumerate() {
+        if line.ends_with([' ', '\t']) {
+            problems.push(format!("line {} has trailing whitespace", index + 1));
+        }
+    }
+    problems
+}
+
+/// Trailer key used by `--record-change-number`.
+const CHANGE_NUMBER_TRAILER_KEY: &str = "Gerrit-Change";
+
+/// The order in which heads are pushed, see `gerrit.upload.order`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PushOrder {
+    /// Older heads are pushed first.
+    Topo,
+    /// Newer heads are pushed first.
+    ReverseTopo,
+}
+
+/// Number of revisions `--stack` may select before asking for confirmation,
+/// unless `gerrit.upload.confirm-threshold` is set.
+const STACK_CONFIRM_THRESHOLD: usize = 10;
+
+#[tracing::instrument(skip_all)]
+pub fn cmd_gerrit_upload(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &UploadArgs,
+) -> Result<(), CommandError> {
+    let revision_args = if args.stack {
+        vec![RevisionArg::from("@::".to_owned())]
+    } else if args.revisions.is_empty() {
+        vec![RevisionArg::AT]
+    } else {
+        args.revisions.clone()
+    };
+    let (mut workspace_command, revisions, to_upload) = if args.skip_snapshot {
+        let mut workspace_command = command.workspace_helper_no_snapshot(ui)?;
+        let (revisions, to_upload) = resolve_upload_set(ui, &workspace_command, &revision_args)?;
+        if uploads_working_copy(&workspace_command, &to_upload)? {
+            tracing::debug!("working copy is uploaded, snapshotting it");
+            workspace_command.maybe_snapshot(ui)?;
+            let (revisions, to_upload) =
+                resolve_upload_set(ui, &workspace_command, &revision_args)?;
+            (workspace_command, revisions, to_upload)
+        } else {
+            (workspace_command, revisions, to_upload)
+        }
+    } else {
+        let workspace_command = command.workspace_helper(ui)?;
+        let (revisions, to_upload) = resolve_upload_set(ui, &workspace_command, &revision_args)?;
+        (workspace_command, revisions, to_upload)
+    };
+    if revisions.is_empty() {
+        writeln!(ui.status(), "No revisions to upload.")?;
+        return Ok(());
+    }
+    let (to_upload, revisions) =
+        match remove_skipped_revisions(ui, command, &workspace_command, to_upload)? {
+            SkipResult::Kept(kept) => {
+                let revisions = kept.iter().map(|commit| commit.id().clone()).collect();
+                (kept, revisions)
+            }
+            SkipResult::Unchanged(to_upload) => (to_upload, revisions),
+        };
+    if to_upload.is_empty() {
+        writeln!(ui.status(), "No revisions to upload.")?;
+        return Ok(());
+    }
+
+    if args.show_upload_set {
+        return write_upload_set(
+            ui,
+            command,
+            &workspace_command,
+            &to_upload,
+            args.no_change_id,
+        );
+    }
+    if let Some([old, new]) = args.replace_change_id.as_deref() {
+        return replace_change_id(ui, command, &mut workspace_command, &to_upload, old, new);
+    }
+
+    let rebase_on = args
+        .rebase_on
+        .as_ref()
+        .map(|arg| workspace_command.resolve_single_rev(ui, arg))
+        .transpose()?;
+    if let Some(destination) = &rebase_on {
+        for commit in &to_upload {
+            if workspace_command
+                .repo()
+                .index()
+                .is_ancestor(commit.id(), destination.id())?
+            {
+                return Err(user_error(format!(
+                    "Cannot rebase revision {} onto its own descendant {}",
+                    short_change_hash(commit.change_id()),
+                    short_change_hash(destination.change_id()),
+                )));
+            }
+        }
+    }
+
+    // Heads are returned in reverse topological order.
+    let mut old_heads = workspace_command
+        .repo()
+        .index()
+        .heads(&mut revisions.iter())
+        .map_err(internal_error)?;
+    match command.settings().get("gerrit.upload.order")? {
+        PushOrder::Topo => old_heads.reverse(),
+        PushOrder::ReverseTopo => {}
+    }
+    if revision_args.len() > 1 {
+        write_ancestor_notes(
+            ui,
+            &workspace_command,
+            &revision_args,
+            &revisions,
+            &old_heads,
+        )?;
+    }
+
+    let repo_defaults =
+        load_repo_defaults(ui, command.settings(), workspace_command.workspace_root())?;
+    let mut subprocess_options = GitSubprocessOptions::from_settings(command.settings())?;
+    if let Some(proxy) = command.settings().get_string("gerrit.proxy").optional()? {
+        add_git_config_env(&mut subprocess_options, "http.proxy", &proxy);
+    }
+    let remote = calculate_remote(
+        workspace_command.repo().store(),
+        command.settings(),
+        args.remote.as_deref(),
+    )?;
+    tracing::debug!(remote = remote.value, "resolved Gerrit remote");
+    let plan = UploadPlanner {
+        args,
+        settings: command.settings(),
+        repo: workspace_command.repo().as_ref(),
+        remote: remote.value.as_ref(),
+        repo_defaults: &repo_defaults,
+    }
+    .plan(
+        &to_upload,
+        &old_heads,
+        rebase_on.map(|commit| commit.id().clone()),
+    )?;
+    write_plan(ui, args, &remote, &plan)?;
+
+    let dry_run = plan.options.dry_run;
+    if !dry_run && !args.yes && !confirm_upload(ui, args, &plan, to_upload.len(), old_heads.len())?
+    {
+        writeln!(ui.status(), "Upload aborted.")?;
+        return Ok(());
+    }
+
+    // Note: This transaction is intentionally never finished. This way, the
+    // Change-Id is never part of the commit description in jj.
+    // This avoids scenarios where you have many commits with the same
+    // Change-Id, or a single commit with many Change-Ids after running
+    // jj split / jj squash respectively.
+    // If a user doesn't like this behavior, they can add the following to
+    // their Cargo.toml.
+    // commit_trailers = 'if(!trailers.contains_key("Change-Id"),
+    // format_gerrit_change_id_trailer(self))'
+    // Since nothing is recorded until a transaction is finished, errors below
+    // don't need to abort it; dropping it discards the rewrites.
+    let mut tx = workspace_command.start_transaction();
+    let after_push: Option<CommandNameAndArgs> = command
+        .settings()
+        .get("gerrit.upload.after-push")
+        .optional()?;
+    let to_upload_ids = to_upload
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
+    let report = upload_commits(
+        ui,
+        &mut tx,
+        &subprocess_options,
+        remote.value.as_ref(),
+        to_upload,
+        &plan.push_targets,
+        &plan.options,
+    )?;
+    if let Some(after_push) = &after_push
+        && !dry_run
+    {
+        let payload = AfterPushPayload::new(
+            &remote.value,
+            &to_upload_ids,
+            &report,
+            &plan.options.change_id_trailer_key,
+        );
+        run_after_push_hook(ui, after_push, &payload)?;
+    }
+    if args.open && !dry_run {
+        open_change_urls(ui, command, &report.change_urls)?;
+    }
+    if dry_run
+        && command
+            .settings()
+            .get_bool("gerrit.upload.strip-change-id-on-dry-run")?
+    {
+        let store = tx.base_repo().store().clone();
+        write_added_change_ids(ui, &store, &to_upload_ids, &report)?;
+    }
+    if let Some(dir_arg) = &args.dump_descriptions {
+        dump_descriptions(ui, command, dir_arg, &report)?;
+    }
+    if args.record_change_number {
+        for id in &to_upload_ids {
+            if !report.change_numbers.contains_key(id) {
+                writeln!(
+                    ui.warning_default(),
+                    "Could not determine the Gerrit change number of revision {}",
+                    short_change_hash(report.uploaded_commits[id].change_id())
+                )?;
+            }
+        }
+        record_change_numbers(
+            ui,
+            &mut workspace_command,
+            &plan.options.change_id_trailer_key,
+            &report.change_numbers,
+        )?;
+    }
+    Ok(())
+}
+
+/// Result of [`remove_skipped_revisions()`].
+enum SkipResult {
+    /// `gerrit.skip` isn't set.
+    Unchanged(Vec<Commit>),
+    /// The revisions that don't match `gerrit.skip`.
+    Kept(Vec<Commit>),
+}
+
+/// Removes the revisions matching `gerrit.skip` from `to_upload`.
+///
+/// Since pushing a revision also pushes its ancestors, they can only be
+/// skipped if none of the uploaded revisions is based on them.
+fn remove_skipped_revisions(
+    ui: &Ui,
+    command: &CommandHelper,
+    workspace_command: &WorkspaceCommandHelper,
+    to_upload: Vec<Commit>,
+) -> Result<SkipResult, CommandError> {
+    let Some(skip_revset) = command.settings().get_string("gerrit.skip").optional()? else {
+        return Ok(SkipResult::Unchanged(to_upload));
+    };
+    let is_skipped = workspace_command
+        .parse_revset(ui, &RevisionArg::from(skip_revset))?
+        .evaluate()?
+        .containing_fn();
+    let mut kept = vec![];
+    let mut skipped = vec![];
+    for commit in to_upload {
+        if is_skipped(commit.id())? {
+            skipped.push(commit);
+        } else {
+            kept.push(commit);
+        }
+    }
+    for skipped_commit in &skipped {
+        for commit in &kept {
+            if workspace_command
+                .repo()
+                .index()
+                .is_ancestor(skipped_commit.id(), commit.id())?
+            {
+                return Err(user_error_with_hint(
+                    format!(
+                        "Cannot skip revision {} because revision {} is based on it",
+                        short_change_hash(skipped_commit.change_id()),
+                        short_change_hash(commit.change_id()),
+                    ),
+                    "Revisions matching `gerrit.skip` must not have descendants among the \
+                     revisions to upload",
+                ));
+            }
+        }
+    }
+    if let Some(mut formatter) = ui.status_formatter()
+        && !skipped.is_empty()
+    {
+        writeln!(
+            formatter,
+            "Skipping {} revisions matching `gerrit.skip`:",
+            skipped.len()
+        )?;
+        for commit in &skipped {
+            write!(formatter, "  ")?;
+            workspace_command.write_commit_summary(formatter.as_mut(), commit)?;
+            writeln!(formatter)?;
+        }
+    }
+    Ok(SkipResult::Kept(kept))
+}
+
+/// Lists `to_upload` for `--show-upload-set`, marking whether each revision
+/// has a `Change-Id` or would get a new one.
+fn write_upload_set(
+    ui: &Ui,
+    command: &CommandHelper,
+    workspace_command: &WorkspaceCommandHelper,
+    to_upload: &[Commit],
+    no_change_id: bool,
+) -> Result<(), CommandError> {
+    let change_id_trailer_key = command
+        .settings()
+        .get_string("gerrit.change-id-trailer-key")?;
+    let mut formatter = ui.stdout_formatter();
+    for commit in to_upload {
+        let has_change_id = parse_description_trailers(commit.description())
+            .iter()
+            .any(|trailer| trailer.key == change_id_trailer_key);
+        workspace_command.write_commit_summary(formatter.as_mut(), commit)?;
+        if has_change_id {
+            writeln!(formatter, " (has {change_id_trailer_key})")?;
+        } else if no_change_id {
+            writeln!(formatter, " (no {change_id_trailer_key})")?;
+        } else {
+            writeln!(formatter, " (new {change_id_trailer_key})")?;
+        }
+    }
+    Ok(())
+}
+
+/// Tells the user about revisions given separately that aren't pushed on their
+/// own, because each head is pushed with all of its ancestors.
+fn write_ancestor_notes(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    revision_args: &[RevisionArg],
+    revisions: &[CommitId],
+    heads: &[CommitId],
+) -> Result<(), CommandError> {
+    let Some(mut formatter) = ui.status_formatter() else {
+        return Ok(());
+    };
+    let repo = workspace_command.repo();
+    for arg in revision_args {
+        let arg_ids: Vec<CommitId> = workspace_command
+            .parse_revset(ui, arg)?
+            .evaluate_to_commit_ids()?
+            .filter_ok(|id| revisions.contains(id))
+            .try_collect()?;
+        let Some(arg_head) = arg_ids.first() else {
+            continue;
+        };
+        if arg_ids.iter().any(|id| heads.contains(id)) {
+            continue;
+        }
+        let containing_head = heads
+            .iter()
+            .find_map(|head| match repo.index().is_ancestor(arg_head, head) {
+                Ok(true) => Some(Ok(head)),
+                Ok(false) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .transpose()
+            .map_err(internal_error)?;
+        if let Some(head) = containing_head {
+            let head = repo.store().get_commit(head)?;
+            write!(
+                formatter,
+                "Not pushing {arg} separately because it is an ancestor of "
+            )?;
+            workspace_command.write_commit_summary(formatter.as_mut(), &head)?;
+            writeln!(formatter)?;
+        }
+    }
+    Ok(())
+}
+
+/// Tells the user about the parts of `plan` they should know about before
+/// anything is uploaded, and prints its provenance with `--verbose`.
+fn write_plan(
+    ui: &Ui,
+    args: &UploadArgs,
+    remote: &Resolved,
+    plan: &UploadPlan,
+) -> Result<(), CommandError> {
+    if let Some(branch) = &plan.assumed_branch {
+        writeln!(
+            ui.hint_default(),
+            "Assuming target branch '{branch}' from `gerrit.assume-default-branch`. Set \
+             `gerrit.default-remote-branch` or pass `--remote-branch` to choose another one."
+        )?;
+    }
+    if args.submit {
+        writeln!(
+            ui.warning_default(),
+            "The uploaded changes will be merged into {} immediately if they meet all submit \
+             requirements",
+            quote_branches(&plan.target_branches())
+        )?;
+    }
+    if !plan.repo_reviewers.is_empty() {
+        // Reviewers see the uploaded changes, so don't add the ones from the
+        // checked-in file without telling the user.
+        writeln!(
+            ui.status(),
+            "Adding reviewers from {REPO_DEFAULTS_FILE_NAME}: {}",
+            plan.repo_reviewers.iter().join(", ")
+        )?;
+    }
+    if args.verbose
+        && let Some(mut formatter) = ui.status_formatter()
+    {
+        writeln!(formatter, "Resolved upload settings:")?;
+        writeln!(
+            formatter,
+            "  remote: {} (from {})",
+            remote.value, remote.source
+        )?;
+        for Resolved { value, source } in &plan.branch_sources {
+            writeln!(formatter, "  branch: {value} (from {source})")?;
+        }
+        match &plan.topic {
+            Some(Resolved { value, source }) => {
+                writeln!(formatter, "  topic: {value} (from {source})")?;
+            }
+            None => writeln!(formatter, "  topic: none")?,
+        }
+        if plan.reviewers.is_empty() {
+            writeln!(formatter, "  reviewers: none")?;
+        }
+        for Resolved { value, source } in &plan.reviewers {
+            writeln!(formatter, "  reviewer: {value} (from {source})")?;
+        }
+        for Resolved { value, source } in &plan.cc {
+            writeln!(formatter, "  cc: {value} (from {source})")?;
+        }
+    }
+    for commit in &plan.empty_revisions {
+        writeln!(
+            ui.warning_default(),
+            "Uploading revision {} even though it is empty",
+            short_change_hash(commit.change_id())
+        )?;
+    }
+    Ok(())
+}
+
+/// Asks for confirmation if `--confirm` was passed or a threshold is exceeded,
+/// and returns whether to upload.
+fn confirm_upload(
+    ui: &Ui,
+    args: &UploadArgs,
+    plan: &UploadPlan,
+    revisions: usize,
+    heads: usize,
+) -> Result<bool, CommandError> {
+    if !args.confirm && !plan.exceeds_threshold {
+        return Ok(true);
+    }
+    if args.stack && plan.exceeds_threshold {
+        writeln!(
+            ui.warning_default(),
+            "`--stack` selected {revisions} revisions to upload"
+        )?;
+    }
+    let target_branches = plan.target_branches();
+    let prompt = if target_branches.len() > 1 {
+        format!(
+            "About to upload {revisions} revisions ({heads} heads) to Gerrit branches {}. \
+             Continue?",
+            quote_branches(&target_branches)
+        )
+    } else {
+        format!("About to upload {revisions} revisions ({heads} heads) to Gerrit. Continue?")
+    };
+    if !Ui::can_prompt() {
+        return Err(user_error_with_hint(
+            format!("Refusing to upload {revisions} revisions without confirmation"),
+            if args.confirm {
+                "Pass `--yes` instead of `--confirm` to upload without confirmation"
+            } else {
+                "Pass `--yes` to upload without confirmation"
+            },
+        ));
+    }
+    Ok(ui.prompt_yes_no(&prompt, None)?)
+}
+
+/// Formats `branches` as a comma-separated list of quoted names.
+fn quote_branches(branches: &[&str]) -> String {
+    branches
+        .iter()
+        .map(|branch| format!("'{branch}'"))
+        .join(", ")
+}
+
+/// Opens the uploaded changes for `--open`.
+fn open_change_urls(
+    ui: &Ui,
+    command: &CommandHelper,
+    change_urls: &[String],
+) -> Result<(), CommandError> {
+    if change_urls.is_empty() {
+        writeln!(
+            ui.warning_default(),
+            "Gerrit didn't report any change URLs, so there is nothing to open"
+        )?;
+        return Ok(());
+    }
+    let browser = match command
+        .settings()
+        .get::<CommandNameAndArgs>("gerrit.browser")
+        .optional()?
+    {
+        Some(browser) => browser,
+        None => default_browser(),
+    };
+    for url in change_urls {
+        open_in_browser(ui, &browser, url)?;
+    }
+    Ok(())
+}
+
+/// Shows the descriptions that a dry-run would have uploaded with a different
+/// `Change-Id` than the local ones, see
+/// `gerrit.upload.strip-change-id-on-dry-run`.
+fn write_added_change_ids(
+    ui: &Ui,
+    store: &Arc<Store>,
+    to_upload: &[CommitId],
+    report: &UploadReport,
+) -> Result<(), CommandError> {
+    let Some(mut formatter) = ui.status_formatter() else {
+        return Ok(());
+    };
+    for id in to_upload {
+        let original = store.get_commit(id)?;
+        let uploaded = &report.uploaded_commits[id];
+        if original.description() == uploaded.description() {
+            continue;
+        }
+        let change_hash = short_change_hash(original.change_id());
+        writeln!(formatter, "Description of {change_hash} without Change-Id:")?;
+        write_quoted_description(formatter.as_mut(), original.description())?;
+        writeln!(formatter, "Description of {change_hash} with Change-Id:")?;
+        write_quoted_description(formatter.as_mut(), uploaded.description())?;
+    }
+    Ok(())
+}
+
+/// Writes the uploaded descriptions to `dir_arg` for `--dump-descriptions`.
+fn dump_descriptions(
+    ui: &Ui,
+    command: &CommandHelper,
+    dir_arg: &Path,
+    report: &UploadReport,
+) -> Result<(), CommandError> {
+    let dir = command.cwd().join(dir_arg);
+    fs::create_dir_all(&dir).context(&dir)?;
+    for commit in report.uploaded_commits.values() {
+        let path = dir.join(short_change_hash(commit.change_id()));
+        fs::write(&path, commit.description()).context(&path)?;
+    }
+    writeln!(
+        ui.status(),
+        "Wrote {} descriptions to {}",
+        report.uploaded_commits.len(),
+        dir_arg.display()
+    )?;
+    Ok(())
+}
+
+/// Returns the command opening URLs in the system's default browser.
+fn default_browser() -> CommandNameAndArgs {
+    if cfg!(windows) {
+        // The first quoted argument of `start` is the window title.
+        CommandNameAndArgs::from(r#"cmd /C start """#)
+    } else if cfg!(target_os = "macos") {
+        CommandNameAndArgs::from("open")
+    } else {
+        CommandNameAndArgs::from("xdg-open")
+    }
+}
+
+/// Opens `url` by passing it as the last argument to `browser`.
+///
+/// The changes have already been uploaded at this point, so failures are only
+/// reported as warnings.
+fn open_in_browser(ui: &Ui, browser: &CommandNameAndArgs, url: &str) -> Result<(), CommandError> {
+    let mut cmd = browser.to_command();
+    cmd.arg(url).stdin(Stdio::null()).stdout(Stdio::null());
+    tracing::info!(?cmd, "opening change in browser");
+    match cmd.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => writeln!(
+            ui.warning_default(),
+            "Browser '{}' exited with {status} while opening {url}",
+            browser.split_name()
+        )?,
+        Err(err) => writeln!(
+            ui.warning_default(),
+            "Failed to open {url} with browser '{}': {err}",
+            browser.split_name()
+        )?,
+    }
+    Ok(())
+}
+
+/// Data passed to the `gerrit.upload.after-push` hook as JSON.
+#[derive(serde::Serialize)]
+struct AfterPushPayload<'a> {
+    remote: &'a str,
+    heads: Vec<AfterPushHead>,
+    changes: Vec<AfterPushChange>,
+}
+
+#[derive(serde::Serialize)]
+struct AfterPushHead {
+    commit: String,
+    #[serde(rename = "ref")]
+    remote_ref: String,
+}
+
+#[derive(serde::Serialize)]
+struct AfterPushChange {
+    change_id: Option<String>,
+    commit: String,
+    number: Option<u64>,
+}
+
+impl<'a> AfterPushPayload<'a> {
+    fn new(
+        remote: &'a str,
+        to_upload: &[CommitId],
+        report: &UploadReport,
+        change_id_trailer_key: &str,
+    ) -> Self {
+        let changes = to_upload
+            .iter()
+            .map(|id| {
+                let commit = &report.uploaded_commits[id];
+                let change_id = parse_description_trailers(commit.description())
+                    .into_iter()
+                    .find(|trailer| trailer.key == change_id_trailer_key)
+                    .map(|trailer| trailer.value);
+                AfterPushChange {
+                    change_id,
+                    commit: commit.id().hex(),
+                    number: report.change_numbers.get(id).copied(),
+                }
+            })
+            .collect();
+        let heads = report
+            .heads
+            .iter()
+            .map(|head| AfterPushHead {
+                commit: head.uploaded.hex(),
+                remote_ref: head.remote_ref.clone(),
+            })
+            .collect();
+        Self {
+            remote,
+            heads,
+            changes,
+        }
+    }
+}
+
+/// Runs the `gerrit.upload.after-push` hook with `payload` on its stdin.
+///
+/// The changes have already been uploaded at this point, so failures are only
+/// reported as warnings.
+fn run_after_push_hook(
+    ui: &Ui,
+    hook: &CommandNameAndArgs,
+    payload: &AfterPushPayload,
+) -> Result<(), CommandError> {
+    let run = || -> Result<ExitStatus, io::Error> {
+        let mut cmd = hook.to_command();
+        cmd.stdin(Stdio::piped());
+        tracing::info!(?cmd, "running after-push hook");
+        let mut child = cmd.spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        // The hook may exit without reading its input
+        if let Err(err) = serde_json::to_writer(&mut stdin, payload)
+            && err.io_error_kind() != Some(io::ErrorKind::BrokenPipe)
+        {
+            return Err(err.into());
+        }
+        drop(stdin);
+        child.wait()
+    };
+    match run() {
+        Ok(status) if status.success() => {}
+        Ok(status) => writeln!(
+            ui.warning_default(),
+            "After-push hook '{}' exited with {status}",
+            hook.split_name()
+        )?,
+        Err(err) => writeln!(
+            ui.warning_default(),
+            "Failed to run after-push hook '{}': {err}",
+            hook.split_name()
+        )?,
+    }
+    Ok(())
+}
+
+/// Adds a `Gerrit-Change` trailer with the reported change number to each of
+/// the local commits in `change_numbers`.
+fn record_change_numbers(
+    ui: &Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    change_id_key: &str,
+    change_numbers: &HashMap<CommitId, u64>,
+) -> Result<(), CommandError> {
+    let repo = workspace_command.repo().clone();
+    let mut descriptions: HashMap<CommitId, String> = HashMap::new();
+    for (commit_id, number) in change_numbers {
+        let commit = repo.store().get_commit(commit_id)?;
+        let number = number.to_string();
+        let recorded = parse_description_trailers(commit.description())
+            .into_iter()
+            .find(|trailer| trailer.key == CHANGE_NUMBER_TRAILER_KEY);
+        match recorded {
+            Some(trailer) if trailer.value == number => {}
+            Some(trailer) => {
+                writeln!(
+                    ui.warning_default(),
+                    "Revision {} records Gerrit change {}, but was uploaded as change {number}",
+                    short_change_hash(commit.change_id()),
+                    trailer.value
+                )?;
+            }
+            None => {
+                let trailer = (CHANGE_NUMBER_TRAILER_KEY.to_owned(), number);
+                let description =
+                    add_trailers(commit.description(), change_id_key, &[trailer]).into_owned();
+                descriptions.insert(commit_id.clone(), description);
+            }
+        }
+    }
+    if descriptions.is_empty() {
+        return Ok(());
+    }
+
+    let mut tx = workspace_command.start_transaction();
+    let mut num_recorded = 0;
+    tx.repo_mut().transform_descendants(
+        descriptions.keys().cloned().collect(),
+        async |rewriter| {
+            let old_commit_id = rewriter.old_commit().id().clone();
+            let commit_builder = rewriter.reparent();
+            if let Some(description) = descriptions.get(&old_commit_id) {
+                commit_builder.set_description(description).write()?;
+                num_recorded += 1;
+            } else {
+                commit_builder.write()?;
+            }
+            Ok(())
+        },
+    )?;
+    writeln!(
+        ui.status(),
+        "Recorded Gerrit change numbers in {num_recorded} revisions"
+    )?;
+    tx.finish(ui, "record Gerrit change numbers")
+}
+
+/// Writes `description` with each line prefixed by `>`, so that blank lines
+/// stay visible.
+fn write_quoted_description(formatter: &mut dyn Formatter, description: &str) -> io::Result<()> {
+    for line in description.lines() {
+        if line.is_empty() {
+            writeln!(formatter, ">")?;
+        } else {
+            writeln!(formatter, "> {line}")?;
+        }
+    }
+    if !description.ends_with('\n') {
+        writeln!(formatter, "\\ No newline at end of description")?;
+    }
+    Ok(())
+}
+
+/// Resolves the selected revisions, and the revisions that would be uploaded
+/// along with them.
+fn resolve_upload_set(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    revision_args: &[RevisionArg],
+) -> Result<(Vec<CommitId>, Vec<Commit>), CommandError> {
+    let target_expr = workspace_command
+        .parse_union_revsets(ui, revision_args)?
+        .resolve()?;
+    workspace_command.check_rewritable_expr(&target_expr)?;
+    let revisions: Vec<_> = target_expr
+        .evaluate(workspace_command.repo().as_ref())?
+        .iter()
+        .try_collect()?;
+    if revisions.is_empty() {
+        return Ok((revisions, vec![]));
+    }
+
+    // If you have the changes main -> A -> B, and then run `jj gerrit upload -r B`,
+    // then that uploads both A and B. Thus, we need to ensure that A also
+    // has a Change-ID.
+    // We make an assumption here that all immutable commits already have a
+    // Change-ID.
+    let to_upload: Vec<Commit> = workspace_command
+        .attach_revset_evaluator(
+            workspace_command
+                .env()
+                .immutable_expression()
+                .range(&RevsetExpression::commits(revisions.clone())),
+        )
+        .evaluate_to_commits()?
+        .try_collect()?;
+    Ok((revisions, to_upload))
+}
+
+/// Returns true if the working-copy commit, or one of its descendants, which
+/// would be rewritten by a snapshot, is in `to_upload`.
+fn uploads_working_copy(
+    workspace_command: &WorkspaceCommandHelper,
+    to_upload: &[Commit],
+) -> Result<bool, CommandError> {
+    let Some(wc_commit_id) = workspace_command.get_wc_commit_id() else {
+        return Ok(false);
+    };
+    let index = workspace_command.repo().index();
+    for commit in to_upload {
+        if index
+            .is_ancestor(wc_commit_id, commit.id())
+            .map_err(internal_error)?
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Rewrites the `Change-Id: <old>` trailers of `commits` to `Change-Id: <new>`.
+fn replace_change_id(
+    ui: &Ui,
+    command: &CommandHelper,
+    workspace_command: &mut WorkspaceCommandHelper,
+    commits: &[Commit],
+    old: &str,
+    new: &str,
+) -> Result<(), CommandError> {
+    if !is_valid_change_id(new) {
+        return Err(user_error_with_hint(
+            format!("Invalid Change-Id '{new}'"),
+            "A Change-Id must be 'I' followed by 40 hexadecimal digits",
+        ));
+    }
+    let key = command
+        .settings()
+        .get_string("gerrit.change-id-trailer-key")?;
+    let old_line = format!("{key}: {old}");
+    let new_line = format!("{key}: {new}");
+    let mut descriptions: HashMap<CommitId, String> = HashMap::new();
+    for commit in commits {
+        let has_old_id = parse_description_trailers(commit.description())
+            .iter()
+            .any(|trailer| trailer.key == key && trailer.value == old);
+        if !has_old_id {
+            continue;
+        }
+        let description = commit
+            .description()
+            .split_inclusive('\n')
+            .map(|line| {
+                if line.trim_end() == old_line {
+                    Cow::Owned(format!("{new_line}\n"))
+                } else {
+                    Cow::Borrowed(line)
+                }
+            })
+            .collect();
+        descriptions.insert(commit.id().clone(), description);
+    }
+    if descriptions.is_empty() {
+        return Err(user_error(format!(
+            "No revisions to upload have the {key} '{old}'"
+        )));
+    }
+
+    let mut tx = workspace_command.start_transaction();
+    let mut num_replaced = 0;
+    tx.repo_mut().transform_descendants(
+        descriptions.keys().cloned().collect(),
+        async |rewriter| {
+            let old_commit_id = rewriter.old_commit().id().clone();
+            let commit_builder = rewriter.reparent();
+            if let Some(description) = descriptions.get(&old_commit_id) {
+                commit_builder.set_description(description).write()?;
+                num_replaced += 1;
+            } else {
+                commit_builder.write()?;
+            }
+            Ok(())
+        },
+    )?;
+    writeln!(ui.status(), "Replaced {key} in {num_replaced} revisions")?;
+    tx.finish(ui, format!("replace {key} {old} with {new}"))
+}
+
+/// Options controlling how [`upload_commits()`] rewrites and pushes commits.
+#[derive(Clone, Debug)]
+pub struct UploadOptions {
+    /// Revision to rebase the roots of the uploaded commits onto.
+    pub rebase_on: Option<CommitId>,
+    /// Additional trailers to add to each commit, as key-value pairs.
+    pub trailers: Vec<(String, String)>,
+    /// Whether to push the commits without adding `Change-Id` trailers.
+    pub no_change_id: bool,
+    /// The trailer key to read and write the Gerrit Change-Id from, usually
+    /// `Change-Id`.
+    pub change_id_trailer_key: String,
+    /// What to do with malformed `Change-Id` trailers.
+    pub on_invalid_change_id: InvalidChangeIdPolicy,
+    /// What new `Change-Id` trailers are derived from.
+    pub change_id_from: ChangeIdSource,
+    /// Hex digits padding the jj change id to the 40 digits of a Change-Id
+    /// derived from it.
+    pub change_id_prefix: String,
+    /// Whether to ask the user to confirm or override each new `Change-Id`.
+    pub edit_change_id: bool,
+    /// Whether to replace existing `Change-Id` trailers with derived ones.
+    pub update_change_id: bool,
+    /// Whether to move existing `Change-Id` trailers after all other trailers.
+    pub commit_hook_compatibility: bool,
+    /// Gerrit push options appended to each pushed ref, e.g. `submit`.
+    pub push_options: Vec<String>,
+    /// Whether to print the diff stat of each pushed stack.
+    pub show_diff_stat: bool,
+    /// Whether to only report what would be pushed.
+    pub dry_run: bool,
+    /// Whether to hide remote messages other than the change URLs.
+    pub quiet_remote: bool,
+    /// Whether to push the remaining heads after a head fails to push.
+    pub keep_going: bool,
+    /// Whether to push to `refs/drafts/<branch>` instead of
+    /// `refs/for/<branch>`.
+    pub draft: bool,
+}
+
+/// How [`upload_commits()`] handles malformed `Change-Id` trailers.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InvalidChangeIdPolicy {
+    /// Warn and upload the trailer as-is.
+    #[default]
+    Warn,
+    /// Refuse to upload anything.
+    Error,
+    /// Replace the trailer with a new one, see [`ChangeIdSource`].
+    Fix,
+}
+
+/// A head pushed by [`upload_commits()`].
+#[derive(Clone, Debug)]
+pub struct UploadedHead {
+    /// The local commit that was selected for upload.
+    pub original: CommitId,
+    /// The commit that was pushed, including the `Change-Id` trailers.
+    pub uploaded: CommitId,
+    /// The ref the commit was pushed to, e.g. `refs/for/main`.
+    pub remote_ref: String,
+}
+
+/// Summary of what [`upload_commits()`] did.
+#[derive(Clone, Debug, Default)]
+pub struct UploadReport {
+    /// The commits to upload, keyed by the id of the corresponding local
+    /// commit. Commits which didn't need rewriting map to themselves.
+    pub uploaded_commits: HashMap<CommitId, Commit>,
+    /// The heads that were pushed, or would be pushed on dry-run.
+    pub heads: Vec<UploadedHead>,
+    /// The change numbers reported by Gerrit, keyed by the id of the local
+    /// commit. Commits whose change number couldn't be determined are
+    /// missing.
+    pub change_numbers: HashMap<CommitId, u64>,
+    /// The URLs of the changes reported by Gerrit, in the order they were
+    /// reported.
+    pub change_urls: Vec<String>,
+}
+
+/// Adds `Change-Id` trailers to `to_upload` and pushes each of the
+/// `push_targets` heads to its target branch.
+///
+/// `to_upload` must be in reverse topological order, and must contain all
+/// ancestors of the heads that will be uploaded. The rewritten commits are
+/// only created in `tx`, which the caller is expected to discard.
+#[tracing::instrument(skip_all, fields(remote = remote.as_str(), heads = push_targets.len()))]
+pub fn upload_commits(
+    ui: &Ui,
+    tx: &mut WorkspaceCommandTransaction,
+    subprocess_options: &GitSubprocessOptions,
+    remote: &RemoteName,
+    to_upload: Vec<Commit>,
+    push_targets: &[(CommitId, String)],
+    options: &UploadOptions,
+) -> Result<UploadReport, UploadError> {
+    let to_upload_ids = to_upload
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
+    let all_have_change_ids = !options.no_change_id
+        && !options.update_change_id
+        && to_upload.iter().all(|commit| {
+            parse_description_trailers(commit.description())
+                .iter()
+                .any(|trailer| {
+                    trailer.key == options.change_id_trailer_key
+                        && is_valid_change_id(&trailer.value)
+                })
+        });
+    let uploaded_commits = rewrite_commits_for_upload(ui, tx.repo_mut(), to_upload, options)?;
+    if all_have_change_ids {
+        // Nothing needs rewriting, which could look like a bug otherwise.
+        writeln!(
+            ui.status(),
+            "All revisions to upload already have {} trailers, so none are added",
+            options.change_id_trailer_key
+        )?;
+    }
+
+    let target_branches: Vec<&str> = push_targets
+        .iter()
+        .map(|(_, branch)| branch.as_str())
+        .unique()
+        .collect();
+    writeln!(
+        ui.status(),
+        "Found {} heads to push to Gerrit (remote '{}'), target {} {}{}",
+        push_targets.iter().map(|(head, _)| head).unique().count(),
+        remote.as_str(),
+        if target_branches.len() == 1 {
+            "branch"
+        } else {
+            "branches"
+        },
+        target_branches
+            .iter()
+            .map(|branch| format!("'{branch}'"))
+            .join(", "),
+        if options.draft { " as drafts" } else { "" },
+    )?;
+
+    let store = tx.base_repo().store().clone();
+    let mut heads = Vec::with_capacity(push_targets.len());
+    let mut change_numbers = HashMap::new();
+    let mut pushed_branches: Vec<&str> = vec![];
+    let mut change_urls: Vec<String> = vec![];
+    let mut failed_heads: Vec<(&CommitId, &str)> = vec![];
+    // NOTE (aseipp): because we are pushing everything to the same remote ref,
+    // we have to loop and push each commit one at a time, even though
+    // push_updates in theory supports multiple GitRefUpdates at once, because
+    // we obviously can't push multiple heads to the same ref. Heads targeting
+    // distinct refs are batched, so that they only need a single push.
+    let mut batches: Vec<Vec<(&CommitId, &str)>> = vec![];
+    for (head, branch) in push_targets {
+        let free_batch = batches
+            .iter_mut()
+            .find(|batch| batch.iter().all(|(_, other)| other != branch));
+        match free_batch {
+            Some(batch) => batch.push((head, branch)),
+            None => batches.push(vec![(head, branch)]),
+        }
+    }
+    for batch in &batches {
+        let _span = tracing::info_span!("push_batch", heads = batch.len()).entered();
+        let mut updates = Vec::with_capacity(batch.len());
+        for &(head, branch) in batch {
+            if let Some(mut formatter) = ui.status_formatter() {
+                if options.dry_run {
+                    write!(formatter, "Dry-run: Would push ")?;
+                } else {
+                    write!(formatter, "Pushing ")?;
+                }
+                if target_branches.len() > 1 {
+                    write!(formatter, "to '{branch}': ")?;
+                }
+                // We have to write the old commit here, because until we finish
+                // the transaction (which we don't), the new commit is labeled as
+                // "hidden".
+                tx.base_workspace_helper()
+                    .write_commit_summary(formatter.as_mut(), &store.get_commit(head).unwrap())?;
+                writeln!(formatter)?;
+                if options.show_diff_stat {
+                    write_stack_diff_stat(
+                        ui,
+                        formatter.as_mut(),
+                        tx.base_workspace_helper(),
+                        &to_upload_ids,
+                        head,
+                    )?;
+                }
+            }
+
+            let new_commit = uploaded_commits.get(head).unwrap();
+            let remote_ref = format_push_ref(branch, options.draft, &options.push_options);
+            heads.push(UploadedHead {
+                original: head.clone(),
+                uploaded: new_commit.id().clone(),
+                remote_ref: remote_ref.clone(),
+            });
+            updates.push(GitRefUpdate {
+                qualified_name: remote_ref.into(),
+                // Pushes to a magic ref never move a branch on the remote:
+                // Gerrit creates or updates changes instead, so there's
+                // nothing to lease against.
+                expected_current_target: None,
+                new_target: Some(new_commit.id().clone()),
+            });
+        }
+        if options.dry_run {
+            continue;
+        }
+
+        // how do we get better errors from the remote? 'git push' tells us
+        // about rejected refs AND ALSO '(nothing changed)' when there are no
+        // changes to push, but we don't get that here.
+        let mut sideband_lines = Vec::new();
+        let push_result = with_remote_git_callbacks_capturing_sideband(
+            ui,
+            &mut sideband_lines,
+            !options.quiet_remote,
+            |cb| {
+                if is_remote_url(remote.as_str()) {
+                    git::push_updates_to_url(
+                        tx.repo_mut(),
+                        subprocess_options.clone(),
+                        remote.as_str(),
+                        &updates,
+                        cb,
+                    )
+                } else {
+                    git::push_updates(
+                        tx.repo_mut(),
+                        subprocess_options.clone(),
+                        remote,
+                        &updates,
+                        cb,
+                    )
+                }
+            },
+        );
+        if options.quiet_remote {
+            // Show everything if something went wrong, since the messages
+            // likely explain why.
+            let succeeded = push_result.as_ref().is_ok_and(|stats| stats.all_ok());
+            if !succeeded {
+                for line in &sideband_lines {
+                    writeln!(ui.status(), "remote: {line}")?;
+                }
+            }
+        }
+        let push_stats = push_result
+            // Despite the fact that a manual git push will error out with 'no new
+            // changes' if you're up to date, this git backend appears to silently
+            // succeed - no idea why.
+            // It'd be nice if we could distinguish this. We should ideally succeed,
+            // but give the user a warning.
+            .map_err(|err| match err {
+                git::GitPushError::NoSuchRemote(_)
+                | git::GitPushError::RemoteName(_)
+                | git::GitPushError::UnexpectedBackend(_) => UploadError::Push(err),
+                git::GitPushError::Subprocess(ref subprocess_err)
+                    if is_connection_error(&subprocess_err.to_string()) =>
+                {
+                    UploadError::Connect(err)
+                }
+                git::GitPushError::Subprocess(_) => UploadError::Subprocess(err),
+            });
+        let push_stats = match push_stats {
+            Ok(push_stats) => push_stats,
+            Err(err) if options.keep_going => {
+                writeln!(ui.warning_default(), "{err}")?;
+                print_error_sources(ui, err.source())?;
+                failed_heads.extend(batch);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        tracing::debug!(all_ok = push_stats.all_ok(), "pushed heads");
+        print_push_stats(ui, &push_stats)?;
+        for (option, message) in find_ignored_push_options(&sideband_lines, &options.push_options) {
+            writeln!(
+                ui.warning_default(),
+                "Gerrit ignored option '{option}': {message}"
+            )?;
+        }
+        let mut rejected_targets = vec![];
+        if !push_stats.all_ok() && options.keep_going {
+            // Git updates each ref of a push separately, so the other heads of
+            // the batch may well have been uploaded.
+            let rejected_refs = push_stats
+                .rejected
+                .iter()
+                .chain(&push_stats.remote_rejected)
+                .map(|(name, _)| name)
+                .collect_vec();
+            rejected_targets = iter::zip(batch, &updates)
+                .filter(|(_, update)| rejected_refs.contains(&&update.qualified_name))
+                .map(|(&target, _)| target)
+                .collect_vec();
+            // If no ref was rejected, we can't tell which heads failed.
+            if rejected_targets.is_empty() {
+                rejected_targets = batch.clone();
+            }
+            failed_heads.extend(&rejected_targets);
+            if rejected_targets.len() == batch.len() {
+                continue;
+            }
+        } else if !push_stats.all_ok() {
+            let uploaded_branches = if target_branches.len() > 1 {
+                pushed_branches
+                    .iter()
+                    .map(|&branch| branch.to_owned())
+                    .collect()
+            } else {
+                vec![]
+            };
+            return Err(UploadError::Rejected { uploaded_branches });
+        }
+        for &(head, branch) in batch {
+            if rejected_targets.contains(&(head, branch)) {
+                continue;
+            }
+            if !pushed_branches.contains(&branch) {
+                pushed_branches.push(branch);
+            }
+        }
+        for (url, number, subject) in parse_change_urls(&sideband_lines) {
+            if !change_urls.iter().any(|known| known == url) {
+                change_urls.push(url.to_owned());
+            }
+            // Gerrit only reports the (possibly truncated) subject of each
+            // change, so find the Change-Id of the commit which already
+            // records the change number, or else of the one with a matching
+            // subject. Ambiguous subjects are skipped.
+            let change_id_of = |commit: &Commit| {
+                parse_description_trailers(commit.description())
+                    .into_iter()
+                    .find(|trailer| trailer.key == options.change_id_trailer_key)
+                    .map(|trailer| trailer.value)
+            };
+            let records_number = |commit: &&Commit| {
+                parse_description_trailers(commit.description())
+                    .iter()
+                    .any(|trailer| {
+                        trailer.key == CHANGE_NUMBER_TRAILER_KEY
+                            && trailer.value == number.to_string()
+                    })
+            };
+            let has_subject = |commit: &&Commit| {
+                let first_line = commit.description().lines().next().unwrap_or("");
+                match subject.strip_suffix("...") {
+                    Some(prefix) => first_line.starts_with(prefix),
+                    None => first_line == subject,
+                }
+            };
+            let mut change_ids = uploaded_commits
+                .values()
+                .filter(records_number)
+                .filter_map(change_id_of)
+                .unique()
+                .collect_vec();
+            if change_ids.is_empty() {
+                change_ids = uploaded_commits
+                    .values()
+                    .filter(has_subject)
+                    .filter_map(change_id_of)
+                    .unique()
+                    .collect_vec();
+            }
+            if let [change_id] = &change_ids[..] {
+                for (commit_id, commit) in &uploaded_commits {
+                    if change_id_of(commit).as_ref() == Some(change_id) {
+                        change_numbers.insert(commit_id.clone(), number);
+                    }
+                }
+            }
+        }
+    }
+    // Older Gerrit versions don't report change URLs, so only print the
+    // heading if there's something to show.
+    if !change_urls.is_empty()
+        && let Some(mut formatter) = ui.status_formatter()
+    {
+        writeln!(formatter, "View change at:")?;
+        for url in &change_urls {
+            writeln!(formatter, "  {url}")?;
+        }
+    }
+    if !failed_heads.is_empty() {
+        report_failed_heads(ui, tx, &batches, &failed_heads, target_branches.len() > 1)?;
+        return Err(UploadError::FailedHeads {
+            failed: failed_heads.len(),
+            total: push_targets.len(),
+        });
+    }
+    Ok(UploadReport {
+        uploaded_commits,
+        heads,
+        change_numbers,
+        change_urls,
+    })
+}
+
+/// Writes the diff stat of the part of `to_upload` ending at `head`, relative
+/// to the parents of its roots.
+fn write_stack_diff_stat(
+    ui: &Ui,
+    formatter: &mut dyn Formatter,
+    workspace_command: &WorkspaceCommandHelper,
+    to_upload: &[CommitId],
+    head: &CommitId,
+) -> Result<(), UploadError> {
+    let repo = workspace_command.repo();
+    let roots: Vec<Commit> = RevsetExpression::commits(to_upload.to_vec())
+        .intersection(&RevsetExpression::commit(head.clone()).ancestors())
+        .roots()
+        .evaluate(repo.as_ref())?
+        .iter()
+        .commits(repo.store())
+        .try_collect()?;
+    // Collect parents outside of revset to preserve parent order
+    let parents: IndexSet<_> = roots.iter().flat_map(|c| c.parents()).try_collect()?;
+    let parents = parents.into_iter().collect_vec();
+    let from_tree = merge_commit_trees(repo.as_ref(), &parents).block_on()?;
+    let to_tree = repo.store().get_commit(head)?.tree();
+    workspace_command
+        .diff_renderer(vec![DiffFormat::Stat(Box::default())])
+        .show_diff(
+            ui,
+            formatter,
+            Diff::new(&from_tree, &to_tree),
+            &EverythingMatcher,
+            &CopyRecords::default(),
+            ui.term_width(),
+        )
+        .block_on()?;
+    Ok(())
+}
+
+/// Rewrites `to_upload` (in reverse topological order) so that every commit
+/// has a `Change-Id` trailer, rebasing descendants onto the rewritten commits.
+///
+/// Returns the commit to upload for each of the original commits.
+#[tracing::instrument(skip_all, fields(commits = to_upload.len()))]
+fn rewrite_commits_for_upload(
+    ui: &Ui,
+    mut_repo: &mut MutableRepo,
+    to_upload: Vec<Commit>,
+    options: &UploadOptions,
+) -> Result<HashMap<CommitId, Commit>, UploadError> {
+    if !options.no_change_id
+        && !options.update_change_id
+        && options.on_invalid_change_id == InvalidChangeIdPolicy::Error
+    {
+        let offenders = to_upload
+            .iter()
+            .filter(|commit| has_invalid_change_id(commit, &options.change_id_trailer_key))
+            .map(|commit| short_change_hash(commit.change_id()))
+            .collect_vec();
+        if !offenders.is_empty() {
+            return Err(UploadError::InvalidChangeIds {
+                key: options.change_id_trailer_key.clone(),
+                revisions: offenders,
+            });
+        }
+    }
+
+    let mut old_to_new: HashMap<CommitId, Commit> = HashMap::new();
+    for original_commit in to_upload.into_iter().rev() {
+        let description = add_trailers(
+            original_commit.description(),
+            &options.change_id_trailer_key,
+            &options.trailers,
+        );
+        let new_description = if options.no_change_id {
+            description.into_owned()
+        } else {
+            description_with_change_id(ui, &original_commit, &description, options)?
+        };
+
+        let is_root = !original_commit
+            .parent_ids()
+            .iter()
+            .any(|id| old_to_new.contains_key(id));
+        let new_parents = match &options.rebase_on {
+            Some(destination) if is_root => vec![destination.clone()],
+            _ => original_commit
+                .parent_ids()
+                .iter()
+                .map(|id| old_to_new.get(id).map_or(id, |p| p.id()).clone())
+                .collect(),
+        };
+
+        if new_description == original_commit.description()
+            && new_parents == original_commit.parent_ids()
+        {
+            // map the old commit to itself
+            old_to_new.insert(original_commit.id().clone(), original_commit);
+            continue;
+        }
+
+        // rewrite the set of parents to point to the commits that were
+        // previously rewritten in toposort order
+        let builder = if options.rebase_on.is_some() {
+            CommitRewriter::new(mut_repo, original_commit.clone(), new_parents)
+                .rebase()
+                .block_on()?
+        } else {
+            mut_repo
+                .rewrite_commit(&original_commit)
+                .set_parents(new_parents)
+        };
+        let new_commit = builder
+            .set_description(new_description)
+            // Set the timestamp back to the timestamp of the original commit.
+            // Otherwise, `jj gerrit upload @ && jj gerrit upload @` will upload
+            // two patchsets with the only difference being the timestamp.
+            .set_committer(original_commit.committer().clone())
+            .set_author(original_commit.author().clone())
+            .write()?;
+
+        // Only conflicts introduced by `--rebase-on` are an error. Commits
+        // which are already conflicted are uploaded as they are.
+        if options.rebase_on.is_some()
+            && new_commit.has_conflict()
+            && !original_commit.has_conflict()
+        {
+            return Err(UploadError::RebaseConflict {
+                revision: short_change_hash(original_commit.change_id()),
+            });
+        }
+
+        old_to_new.insert(original_commit.id().clone(), new_commit);
+    }
+    tracing::debug!(
+        rewritten = old_to_new
+            .iter()
+            .filter(|(old_id, new_commit)| *old_id != new_commit.id())
+            .count(),
+        "prepared commits for upload"
+    );
+    Ok(old_to_new)
+}
+
+/// Returns the description of `commit` with a `Change-Id` trailer added if it
+/// doesn't have one yet.
+fn description_with_change_id(
+    ui: &Ui,
+    commit: &Commit,
+    description: &str,
+    options: &UploadOptions,
+) -> Result<String, UploadError> {
+    let trailers = parse_description_trailers(description);
+
+    let change_id_trailers: Vec<&Trailer> = trailers
+        .iter()
+        .filter(|trailer| trailer.key == options.change_id_trailer_key)
+        .collect();
+
+    // There shouldn't be multiple change-ID fields. So just error out if
+    // there is.
+    if change_id_trailers.len() > 1 {
+        return Err(UploadError::MultipleChangeIds {
+            key: options.change_id_trailer_key.clone(),
+            revision: short_change_hash(commit.change_id()),
+        });
+    }
+
+    // The user can choose to explicitly set their own change-ID to
+    // override the default change-ID based on the jj change-ID.
+    let new_description = if let Some(trailer) = change_id_trailers.first() {
+        if options.update_change_id {
+            let change_id = choose_new_change_id(ui, commit, options)?;
+            if change_id != trailer.value {
+                writeln!(
+                    ui.status(),
+                    "Updating {} footer in revision {}",
+                    options.change_id_trailer_key,
+                    short_change_hash(commit.change_id()),
+                )?;
+                return Ok(move_change_id_trailer_last(
+                    description,
+                    &options.change_id_trailer_key,
+                    &change_id,
+                ));
+            }
+        }
+        // Check the change-id format is correct.
+        if !is_valid_change_id(&trailer.value) {
+            match options.on_invalid_change_id {
+                InvalidChangeIdPolicy::Warn => {
+                    // Intentionally leave the invalid change IDs as-is.
+                    writeln!(
+                        ui.warning_default(),
+                        "Invalid {} footer in revision {}",
+                        options.change_id_trailer_key,
+                        short_change_hash(commit.change_id()),
+                    )?;
+                }
+                // Already rejected before rewriting anything.
+                InvalidChangeIdPolicy::Error => {}
+                InvalidChangeIdPolicy::Fix => {
+                    writeln!(
+                        ui.status(),
+                        "Replacing invalid {} footer in revision {}",
+                        options.change_id_trailer_key,
+                        short_change_hash(commit.change_id()),
+                    )?;
+                    return Ok(move_change_id_trailer_last(
+                        description,
+                        &options.change_id_trailer_key,
+                        &choose_new_change_id(ui, commit, options)?,
+                    ));
+                }
+            }
+        }
+
+        if options.commit_hook_compatibility {
+            move_change_id_trailer_last(description, &options.change_id_trailer_key, &trailer.value)
+        } else {
+            description.to_owned()
+        }
+    } else {
+        // Build the new description in a single allocation, since it may be
+        // large.
+        let description = description.trim();
+        let key = &options.change_id_trailer_key;
+        let change_id = choose_new_change_id(ui, commit, options)?;
+        let mut new_description =
+            String::with_capacity(description.len() + key.len() + change_id.len() + 5);
+        new_description.push_str(description);
+        new_description.push_str(if trailers.is_empty() { "\n\n" } else { "\n" });
+        new_description.push_str(key);
+        new_description.push_str(": ");
+        new_description.push_str(&change_id);
+        new_description.push('\n');
+        new_description
+    };
+    Ok(new_description)
+}
+
+/// Adds `trailers` to the trailer paragraph of `description`, skipping those
+/// which are already present. They are inserted before the `Change-Id` trailer
+/// (named `change_id_key`) so that it stays last.
+fn add_trailers<'a>(
+    description: &'a str,
+    change_id_key: &str,
+    trailers: &[(String, String)],
+) -> Cow<'a, str> {
+    if trailers.is_empty() {
+        return Cow::Borrowed(description);
+    }
+    let existing = parse_description_trailers(description);
+    let new_trailers = trailers
+        .iter()
+        .filter(|(key, value)| {
+            !existing
+                .iter()
+                .any(|trailer| trailer.key == *key && trailer.value == *value)
+        })
+        .unique()
+        .collect_vec();
+    if new_trailers.is_empty() {
+        return Cow::Borrowed(description);
+    }
+    let description = description.trim_end();
+    let capacity = description.len()
+        + 2
+        + new_trailers
+            .iter()
+            .map(|(key, value)| key.len() + value.len() + 3)
+            .sum::<usize>();
+    let change_id_start = if existing.is_empty() {
+        None
+    } else {
+        let paragraph_start = description.rfind("\n\n").map_or(0, |i| i + 2);
+        description[paragraph_start..]
+            .split_inclusive('\n')
+            .scan(paragraph_start, |start, line| {
+                let line_start = *start;
+                *start += line.len();
+                Some((line_start, line))
+            })
+            .find(|(_, line)| {
+                line.split_once(':')
+                    .is_some_and(|(key, _)| key.trim_end() == change_id_key)
+            })
+            .map(|(line_start, _)| line_start)
+    };
+    let (head, change_id_trailer) =
+        description.split_at(change_id_start.unwrap_or(description.len()));
+    let mut new_description = String::with_capacity(capacity);
+    new_description.push_str(head);
+    if change_id_trailer.is_empty() {
+        new_description.push_str(if existing.is_empty() { "\n\n" } else { "\n" });
+    }
+    for (key, value) in new_trailers {
+        new_description.push_str(key);
+        new_description.push_str(": ");
+        new_description.push_str(value);
+        new_description.push('\n');
+    }
+    if !change_id_trailer.is_empty() {
+        new_description.push_str(change_id_trailer);
+        new_description.push('\n');
+    }
+    Cow::Owned(new_description)
+}
+
+/// Returns the Change-Id to add to `commit`, asking the user to confirm it if
+/// `--edit-change-id` was passed.
+fn choose_new_change_id(
+    ui: &Ui,
+    commit: &Commit,
+    options: &UploadOptions,
+) -> Result<String, UploadError> {
+    let derived = new_gerrit_change_id(commit, options.change_id_from, &options.change_id_prefix);
+    if !options.edit_change_id {
+        return Ok(derived);
+    }
+    let change_id = ui.prompt_choice_with(
+        &format!(
+            "{} for revision {} [{derived}]",
+            options.change_id_trailer_key,
+            short_change_hash(commit.change_id())
+        ),
+        Some(&derived),
+        |input| {
+            if is_valid_change_id(input) {
+                Ok(input.to_owned())
+            } else {
+                Err(format!(
+                    "Invalid Change-Id '{input}', expected 'I' followed by 40 hexadecimal digits"
+                ))
+            }
+        },
+    )?;
+    Ok(change_id)
+}
+
+/// Returns true if `commit` has a malformed `Change-Id` trailer named `key`.
+fn has_invalid_change_id(commit: &Commit, key: &str) -> bool {
+    parse_description_trailers(commit.description())
+        .iter()
+        .any(|trailer| trailer.key == key && !is_valid_change_id(&trailer.value))
+}
+
+/// Finds the change URLs Gerrit reports after a push, e.g.
+/// `https://gerrit.example.com/c/repo/+/123 subject [NEW]`, and returns the
+/// URLs and change numbers with the (possibly truncated) subjects.
+fn parse_change_urls(sideband_lines: &[String]) -> Vec<(&str, u64, &str)> {
+    sideband_lines
+        .iter()
+        .filter_map(|line| parse_change_url(line))
+        .collect()
+}
+
+/// Parses a single `<url> <subject>` line reported by Gerrit, returning the
+/// URL, change number and subject.
+fn parse_change_url(line: &str) -> Option<(&str, u64, &str)> {
+    let (url, mut subject) = line.trim().split_once(' ')?;
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return None;
+    }
+    let (_, number) = url.rsplit_once("/+/")?;
+    let number = number.trim_end_matches('/').parse().ok()?;
+    // Strip status markers such as ` [NEW]` or ` [WIP]`
+    while let Some(rest) = subject.strip_suffix(']') {
+        let (rest, _) = rest.rsplit_once(" [")?;
+        subject = rest;
+    }
+    Some((url, number, subject.trim()))
+}
+
+/// Lists the heads which were and weren't uploaded with `--keep-going`.
+fn report_failed_heads(
+    ui: &Ui,
+    tx: &WorkspaceCommandTransaction,
+    batches: &[Vec<(&CommitId, &str)>],
+    failed_heads: &[(&CommitId, &str)],
+    show_branch: bool,
+) -> Result<(), UploadError> {
+    let Some(mut formatter) = ui.status_formatter() else {
+        return Ok(());
+    };
+    let store = tx.base_repo().store();
+    let (failed, uploaded): (Vec<_>, Vec<_>) = batches
+        .iter()
+        .flatten()
+        .partition(|target| failed_heads.contains(target));
+    for (heading, targets) in [("Uploaded heads:", uploaded), ("Failed heads:", failed)] {
+        if targets.is_empty() {
+            continue;
+        }
+        writeln!(formatter, "{heading}")?;
+        for &&(head, branch) in &targets {
+            write!(formatter, "  ")?;
+            if show_branch {
+                write!(formatter, "to '{branch}': ")?;
+            }
+            tx.base_workspace_helper()
+                .write_commit_summary(formatter.as_mut(), &store.get_commit(head)?)?;
+            writeln!(formatter)?;
+        }
+    }
+    Ok(())
+}
+
+/// Finds the push options which Gerrit reported as ignored.
+///
+/// Gerrit acknowledges push options it couldn't apply with a `warning:`
+/// sideband line such as `warning: option r ignored: account 'x' not found`.
+/// A warning matches an option if it says "ignored" and names the option's
+/// key, and for options with a value, also mentions the value. Returns the
+/// matching options along with the warning message.
+fn find_ignored_push_options<'a>(
+    sideband_lines: &'a [String],
+    push_options: &'a [String],
+) -> Vec<(&'a str, &'a str)> {
+    let warnings = sideband_lines.iter().filter_map(|line| {
+        let line = line.trim();
+        let (prefix, message) = line.split_once(':')?;
+        prefix
+            .eq_ignore_ascii_case("warning")
+            .then_some(message.trim())
+    });
+    let mut ignored = vec![];
+    for message in warnings {
+        let words = message
+            .split(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .filter(|word| !word.is_empty())
+            .map(|word| word.to_ascii_lowercase())
+            .collect_vec();
+        if !words.iter().any(|word| word == "ignored") {
+            continue;
+        }
+        for option in push_options {
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key, Some(decode_push_option_value(value))),
+                None => (option.as_str(), None),
+            };
+            let names_key = words.iter().any(|word| word.eq_ignore_ascii_case(key));
+            if names_key && value.is_none_or(|value| message.contains(&value)) {
+                ignored.push((option.as_str(), message));
+            }
+        }
+    }
+    ignored
+}
+
+/// Reverses [`encode_push_option_value()`].
+fn decode_push_option_value(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(str::from_utf8(hex).ok()?, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use testutils::TestRepo;
+    use testutils::create_random_commit;
+
+    use super::*;
+    use crate::gerrit_util::CHANGE_ID_TRAILER_KEY;
+
+    fn upload_options(change_id_from: ChangeIdSource) -> UploadOptions {
+        UploadOptions {
+            rebase_on: None,
+            trailers: vec![],
+            no_change_id: false,
+            change_id_trailer_key: CHANGE_ID_TRAILER_KEY.to_owned(),
+            on_invalid_change_id: InvalidChangeIdPolicy::Warn,
+            change_id_from,
+            change_id_prefix: "6a6a6964".to_owned(),
+            edit_change_id: false,
+            update_change_id: false,
+            commit_hook_compatibility: false,
+            push_options: vec![],
+            show_diff_stat: false,
+            dry_run: false,
+            quiet_remote: false,
+            keep_going: false,
+            draft: false,
+        }
+    }
+
+    #[test]
+    fn test_format_push_ref() {
+        assert_eq!(format_push_ref("main", false, &[]), "refs/for/main");
+        assert_eq!(format_push_ref("main", true, &[]), "refs/drafts/main");
+        assert_eq!(
+            format_push_ref(
+                "release/1.0",
+                false,
+                &["wip".to_owned(), "topic=foo".to_owned()]
+            ),
+            "refs/for/release/1.0%wip,topic=foo"
+        );
+    }
+
+    #[test]
+    fn test_encode_push_option_value() {
+        assert_eq!(encode_push_option_value("abc123"), "abc123");
+        assert_eq!(encode_push_option_value("a b,c%d"), "a%20b%2Cc%25d");
+        assert_eq!(
+            encode_push_option_value("a~b/../c.lock"),
+            "a%7Eb%2F%2E%2E%2Fc%2Elock"
+        );
+        assert_eq!(encode_push_option_value("x-y_z@w"), "x%2Dy%5Fz%40w");
+        assert_eq!(encode_push_option_value("é"), "%C3%A9");
+    }
+
+    #[test]
+    fn test_decode_push_option_value() {
+        for value in ["abc", "a b,c%d", "a~b/../c.lock", "é", ""] {
+            assert_eq!(
+                decode_push_option_value(&encode_push_option_value(value)),
+                value
+            );
+        }
+        assert_eq!(decode_push_option_value("50%"), "50%");
+        assert_eq!(decode_push_option_value("%zz"), "%zz");
+    }
+
+    #[test]
+    fn test_find_ignored_push_options() {
+        let push_options = [
+            "submit".to_owned(),
+            "wip".to_owned(),
+            format!("r={}", encode_push_option_value("alice@example.com")),
+            format!("r={}", encode_push_option_value("bob@example.com")),
+            "topic=foo".to_owned(),
+        ];
+        let sideband_lines = [
+            "warning: option submit ignored: change is not ready for submission",
+            "warning: option r ignored: account 'bob@example.com' not found",
+            // Mentions options without saying they were ignored
+            "warning: 1 change is wip, submit requirements for topic foo not met",
+            // Says something was ignored without naming an option key
+            "warning: ignored 1 commit without a Change-Id",
+            "  hint: option wip ignored",
+        ]
+        .map(str::to_owned);
+        assert_eq!(
+            find_ignored_push_options(&sideband_lines, &push_options),
+            [
+                (
+                    "submit",
+                    "option submit ignored: change is not ready for submission"
+                ),
+                (
+                    "r=bob%40example%2Ecom",
+                    "option r ignored: account 'bob@example.com' not found"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_change_url() {
+        assert_eq!(
+            parse_change_url("  https://gerrit.example.com/c/repo/+/123 subject [NEW]"),
+            Some(("https://gerrit.example.com/c/repo/+/123", 123, "subject"))
+        );
+        assert_eq!(
+            parse_change_url("http://gerrit/c/repo/+/7/ a [b] subject [WIP] [NEW]"),
+            Some(("http://gerrit/c/repo/+/7/", 7, "a [b] subject"))
+        );
+        assert_eq!(
+            parse_change_url("https://gerrit/c/repo/+/8 subject"),
+            Some(("https://gerrit/c/repo/+/8", 8, "subject"))
+        );
+        assert_eq!(parse_change_url("Processing changes: refs: 1"), None);
+        assert_eq!(parse_change_url("ssh://gerrit/c/repo/+/1 subject"), None);
+        assert_eq!(parse_change_url("https://gerrit/c/repo subject"), None);
+        assert_eq!(parse_change_url("https://gerrit/c/repo/+/x subject"), None);
+    }
+
+    #[test]
+    fn test_add_trailers() {
+        let trailers = [("Bug".to_owned(), "123".to_owned())];
+        assert!(matches!(
+            add_trailers("subject\n", "Change-Id", &[]),
+            Cow::Borrowed("subject\n")
+        ));
+        assert_eq!(
+            add_trailers("subject\n", "Change-Id", &trailers),
+            "subject\n\nBug: 123\n"
+        );
+        assert_eq!(
+            add_trailers("subject\n\nChange-Id: I123\n", "Change-Id", &trailers),
+            "subject\n\nBug: 123\nChange-Id: I123\n"
+        );
+        assert_eq!(
+            add_trailers(
+                "subject\n\nChange-Id: I123\nSigned-off-by: a\n",
+                "Change-Id",
+                &trailers
+            ),
+            "subject\n\nBug: 123\nChange-Id: I123\nSigned-off-by: a\n"
+        );
+        assert!(matches!(
+            add_trailers("subject\n\nBug: 123\n", "Change-Id", &trailers),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(
+            add_trailers(
+                "subject\n",
+                "Change-Id",
+                &[
+                    ("Bug".to_owned(), "1".to_owned()),
+                    ("Bug".to_owned(), "1".to_owned()),
+                    ("Bug".to_owned(), "2".to_owned()),
+                ]
+            ),
+            "subject\n\nBug: 1\nBug: 2\n"
+        );
+    }
+
+    #[test]
+    fn test_choose_new_change_id() {
+        let test_repo = TestRepo::init();
+        let mut tx = test_repo.repo.start_transaction();
+        let commit = create_random_commit(tx.repo_mut())
+            .set_description("subject\n")
+            .write()
+            .unwrap();
+        let ui = Ui::null();
+
+        let change_id =
+            choose_new_change_id(&ui, &commit, &upload_options(ChangeIdSource::JjChangeId))
+                .unwrap();
+        assert_eq!(change_id, format!("I{}6a6a6964", commit.change_id().hex()));
+
+        let change_id =
+            choose_new_change_id(&ui, &commit, &upload_options(ChangeIdSource::CommitTree))
+                .unwrap();
+        assert!(is_valid_change_id(&change_id));
+        assert_eq!(
+            change_id,
+            new_gerrit_change_id(&commit, ChangeIdSource::CommitTree, "")
+        );
+        assert_ne!(change_id, format!("I{}6a6a6964", commit.change_id().hex()));
+    }
+}
@@ -0,0 +1,620 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write as _;
+
+use itertools::Itertools as _;
+use jj_lib::backend::CommitId;
+use jj_lib::commit::Commit;
+use jj_lib::config::ConfigGetResultExt as _;
+use jj_lib::ref_name::RemoteName;
+use jj_lib::repo::Repo;
+use jj_lib::settings::UserSettings;
+
+use super::REPO_DEFAULTS_FILE_NAME;
+use super::RepoDefaults;
+use super::STACK_CONFIRM_THRESHOLD;
+use super::UploadArgs;
+use super::UploadOptions;
+use super::calculate_push_ref;
+use super::calculate_topic;
+use super::check_target_branch;
+use super::encode_push_option_value;
+use super::expand_branch_pattern;
+use super::infer_target_branch;
+use super::is_branch_pattern;
+use super::lint_description;
+use super::read_reviewers_file;
+use super::resolve_list_values;
+use crate::cli_util::short_change_hash;
+use crate::command_error::CommandError;
+use crate::command_error::user_error_with_hint;
+use crate::gerrit_util::Resolved;
+use crate::gerrit_util::get_change_id_prefix;
+
+/// Upload settings resolved by [`UploadPlanner`].
+pub(super) struct UploadPlan {
+    /// The heads to push, each with the branch it is pushed to.
+    pub(super) push_targets: Vec<(CommitId, String)>,
+    /// Where each of the target branches comes from.
+    pub(super) branch_sources: Vec<Resolved>,
+    /// The branch assumed from `gerrit.assume-default-branch`, if any.
+    pub(super) assumed_branch: Option<String>,
+    pub(super) topic: Option<Resolved>,
+    pub(super) reviewers: Vec<Resolved>,
+    /// Reviewers added from `.gerrit.toml`, which the user should be told
+    /// about.
+    pub(super) repo_reviewers: Vec<String>,
+    pub(super) cc: Vec<Resolved>,
+    /// Empty revisions which are uploaded anyway because of `--allow-empty`.
+    pub(super) empty_revisions: Vec<Commit>,
+    /// Whether the number of revisions or heads exceeds the thresholds above
+    /// which confirmation is requested.
+    pub(super) exceeds_threshold: bool,
+    pub(super) options: UploadOptions,
+}
+
+impl UploadPlan {
+    /// Returns the distinct target branches in the order they're pushed to.
+    pub(super) fn target_branches(&self) -> Vec<&str> {
+        self.push_targets
+            .iter()
+            .map(|(_, branch)| branch.as_str())
+            .unique()
+            .collect()
+    }
+}
+
+/// Resolves what to upload where from the arguments, the config and the repo's
+/// `.gerrit.toml`, and checks that the revisions may be uploaded.
+///
+/// This neither prints nor prompts anything, which is left to the caller.
+pub(super) struct UploadPlanner<'a> {
+    pub(super) args: &'a UploadArgs,
+    pub(super) settings: &'a UserSettings,
+    pub(super) repo: &'a dyn Repo,
+    pub(super) remote: &'a RemoteName,
+    pub(super) repo_defaults: &'a RepoDefaults,
+}
+
+/// Target branches resolved by [`UploadPlanner::resolve_push_targets()`].
+struct PushTargets {
+    targets: Vec<(CommitId, String)>,
+    sources: Vec<Resolved>,
+    assumed_branch: Option<String>,
+}
+
+impl UploadPlanner<'_> {
+    /// Resolves the plan to upload `to_upload`, whose `heads` are pushed in
+    /// the given order.
+    pub(super) fn plan(
+        &self,
+        to_upload: &[Commit],
+        heads: &[CommitId],
+        rebase_on: Option<CommitId>,
+    ) -> Result<UploadPlan, CommandError> {
+        let PushTargets {
+            targets: push_targets,
+            sources: branch_sources,
+            assumed_branch,
+        } = self.resolve_push_targets(heads)?;
+        let mut push_options = self.resolve_state_options()?;
+        let topic = calculate_topic(
+            self.settings,
+            self.remote.as_str(),
+            self.args.topic.as_deref(),
+            self.repo_defaults,
+        )?;
+        let topic = match (topic, &self.args.topic_prefix) {
+            (Some(topic), Some(prefix)) => Some(Resolved::new(
+                format!("{prefix}{}", topic.value),
+                format!("{} with --topic-prefix", topic.source),
+            )),
+            (None, Some(_)) => {
+                return Err(user_error_with_hint(
+                    "There is no topic to apply --topic-prefix to",
+                    "Pass `--topic`, or configure `gerrit.default-topic`",
+                ));
+            }
+            (topic, None) => topic,
+        };
+        if let Some(topic) = &topic {
+            push_options.push(format!("topic={}", encode_push_option_value(&topic.value)));
+        }
+        let (reviewers, repo_reviewers) = self.resolve_reviewers()?;
+        for reviewer in &reviewers {
+            push_options.push(format!("r={}", encode_push_option_value(&reviewer.value)));
+        }
+        // Gerrit ignores accounts that are both reviewers and CCed, so there's
+        // no need to filter them out here.
+        let cc = self
+            .resolve_list_values(&self.args.cc, "--cc", "cc", "account")?
+            .into_iter()
+            .unique_by(|resolved| resolved.value.clone())
+            .collect_vec();
+        for resolved in &cc {
+            push_options.push(format!("cc={}", encode_push_option_value(&resolved.value)));
+        }
+        if reviewers.is_empty()
+            && cc.is_empty()
+            && self.settings.get_bool("gerrit.require-reviewer")?
+        {
+            return Err(user_error_with_hint(
+                "Refusing to upload changes without reviewers",
+                "Pass `--reviewer` or `--cc`, or configure `gerrit.reviewers` or `gerrit.cc`. \
+                 Reviewers are required because `gerrit.require-reviewer` is enabled",
+            ));
+        }
+        for label in &self.args.labels {
+            push_options.push(format!("l={label}"));
+        }
+        let hashtags = self.resolve_list_values(
+            &self.args.hashtags,
+            "--hashtag",
+            "default-hashtags",
+            "hashtag",
+        )?;
+        for hashtag in hashtags.iter().map(|resolved| &resolved.value).unique() {
+            // Encoding `,` and `%` keeps them from being parsed as the start
+            // of the next option.
+            push_options.push(format!("hashtag={}", encode_push_option_value(hashtag)));
+        }
+
+        let empty_revisions = self.check_revisions(to_upload)?;
+        let options = self.upload_options(rebase_on, push_options)?;
+        let exceeds_threshold = self.exceeds_threshold(to_upload.len(), heads.len())?;
+        Ok(UploadPlan {
+            push_targets,
+            branch_sources,
+            assumed_branch,
+            topic,
+            reviewers,
+            repo_reviewers,
+            cc,
+            empty_revisions,
+            exceeds_threshold,
+            options,
+        })
+    }
+
+    /// Resolves the branch each of `heads` is pushed to.
+    fn resolve_push_targets(&self, heads: &[CommitId]) -> Result<PushTargets, CommandError> {
+        let args = self.args;
+        // Stacks based on different branches must not be pushed to the same
+        // `refs/for/<branch>`, so figure out which branch each head belongs to.
+        let mut inferred_branches = Vec::with_capacity(heads.len());
+        for head in heads {
+            let branch = infer_target_branch(self.repo, self.remote, head)?;
+            inferred_branches.push(branch);
+        }
+        let distinct_branches: Vec<&String> = inferred_branches.iter().flatten().unique().collect();
+        if distinct_branches.len() > 1 && !args.auto_branch {
+            return Err(user_error_with_hint(
+                format!(
+                    "The revisions to upload are based on different branches: {}",
+                    distinct_branches
+                        .iter()
+                        .map(|branch| format!("'{branch}'"))
+                        .join(", ")
+                ),
+                "Upload each stack separately, or use `--auto-branch` to push each stack to the \
+                 branch it is based on",
+            ));
+        }
+        let mut targets: Vec<(CommitId, String)> = Vec::with_capacity(heads.len());
+        let mut sources: Vec<Resolved> = vec![];
+        let mut assumed_branch = None;
+        let branch_pattern = args
+            .remote_branch
+            .as_deref()
+            .filter(|branch| is_branch_pattern(branch));
+        let mut configured_branches: Option<Vec<Resolved>> = None;
+        for (head, inferred_branch) in heads.iter().zip(inferred_branches) {
+            let resolved_branches = match inferred_branch {
+                Some(branch) if args.auto_branch => vec![Resolved::new(
+                    branch,
+                    "the remote bookmark the revisions are based on",
+                )],
+                _ => match &configured_branches {
+                    Some(resolved) => resolved.clone(),
+                    None => {
+                        let resolved = if let Some(pattern) = branch_pattern {
+                            let source = format!("--remote-branch '{pattern}'");
+                            expand_branch_pattern(self.repo, self.remote, pattern)?
+                                .into_iter()
+                                .map(|branch| Resolved::new(branch, &source))
+                                .collect()
+                        } else {
+                            let (resolved, assumed) = calculate_push_ref(
+                                self.settings,
+                                self.remote.as_str(),
+                                args.remote_branch.clone(),
+                                self.repo_defaults,
+                            )?;
+                            if assumed {
+                                assumed_branch = Some(resolved.value.clone());
+                            }
+                            vec![resolved]
+                        };
+                        configured_branches.insert(resolved).clone()
+                    }
+                },
+            };
+            for resolved in resolved_branches {
+                check_target_branch(&resolved.value)?;
+                targets.push((head.clone(), resolved.value.clone()));
+                if !sources.iter().any(|source| source.value == resolved.value) {
+                    sources.push(resolved);
+                }
+            }
+        }
+        if let (Some(pattern), Some(branches)) = (branch_pattern, &configured_branches)
+            && branches.len() > 1
+            && !args.confirm
+            && !args.yes
+            && !args.dry_run
+        {
+            // Uploading to several branches creates a change on each of them,
+            // which is tedious to undo, so make sure the pattern matched what
+            // was meant.
+            return Err(user_error_with_hint(
+                format!(
+                    "--remote-branch '{pattern}' matches {} branches: {}",
+                    branches.len(),
+                    branches
+                        .iter()
+                        .map(|branch| format!("'{}'", branch.value))
+                        .join(", ")
+                ),
+                "Pass `--confirm` to be asked before uploading to all of them, `--yes` to upload \
+                 to all of them without asking, or `--dry-run` to preview the upload",
+            ));
+        }
+        Ok(PushTargets {
+            targets,
+            sources,
+            assumed_branch,
+        })
+    }
+
+    /// Resolves the push options setting the state of the uploaded changes,
+    /// such as `submit` or `wip`.
+    fn resolve_state_options(&self) -> Result<Vec<String>, CommandError> {
+        let args = self.args;
+        let upload_wip = self.settings.get_bool("gerrit.upload-wip")?;
+        let mut push_options = vec![];
+        if args.submit {
+            if !self.settings.get_bool("gerrit.allow-auto-submit")? {
+                return Err(user_error_with_hint(
+                    "Refusing to submit changes on upload",
+                    "Set `gerrit.allow-auto-submit = true` to allow `--submit`",
+                ));
+            }
+            // Gerrit can't submit work-in-progress changes.
+            if !args.ready && upload_wip {
+                return Err(user_error_with_hint(
+                    "Refusing to submit changes uploaded as work in progress",
+                    "`gerrit.upload-wip` is enabled, pass `--ready` to upload the changes as \
+                     ready for review",
+                ));
+            }
+            push_options.push("submit".to_owned());
+        }
+        if args.amend_message {
+            // Gerrit decodes `_` in the patchset message as a space.
+            push_options.push("m=Message_update".to_owned());
+        }
+        if let Some(message) = &args.message {
+            // Gerrit decodes `_` as a space before percent-decoding the
+            // message, so it must be encoded as well.
+            push_options.push(format!("m={}", encode_push_option_value(message)));
+        }
+        if args.wip || (!args.ready && upload_wip) {
+            push_options.push("wip".to_owned());
+        } else if args.ready {
+            push_options.push("ready".to_owned());
+        }
+        if args.private {
+            push_options.push("private".to_owned());
+        } else if args.remove_private {
+            push_options.push("remove-private".to_owned());
+        }
+        Ok(push_options)
+    }
+
+    /// Resolves the reviewers to add, along with the ones that only come from
+    /// `.gerrit.toml`.
+    fn resolve_reviewers(&self) -> Result<(Vec<Resolved>, Vec<String>), CommandError> {
+        let mut reviewers: Vec<Resolved> = vec![];
+        let mut add_reviewer = |reviewer: &str, source: &str| {
+            let is_new = !reviewers.iter().any(|resolved| resolved.value == reviewer);
+            if is_new {
+                reviewers.push(Resolved::new(reviewer, source));
+            }
+            is_new
+        };
+        for reviewer in
+            self.resolve_list_values(&self.args.reviewers, "--reviewer", "reviewers", "reviewer")?
+        {
+            add_reviewer(&reviewer.value, &reviewer.source);
+        }
+        if let Some(path) = &self.args.reviewers_file {
+            for reviewer in read_reviewers_file(path)? {
+                add_reviewer(&reviewer, "--reviewers-file");
+            }
+        }
+        let repo_reviewers = self
+            .repo_defaults
+            .reviewers
+            .iter()
+            .filter(|reviewer| add_reviewer(reviewer, REPO_DEFAULTS_FILE_NAME))
+            .cloned()
+            .collect_vec();
+        Ok((reviewers, repo_reviewers))
+    }
+
+    fn resolve_list_values(
+        &self,
+        values: &[String],
+        arg: &str,
+        name: &str,
+        what: &str,
+    ) -> Result<Vec<Resolved>, CommandError> {
+        resolve_list_values(self.settings, self.remote.as_str(), values, arg, name, what)
+    }
+
+    /// Rejects revisions that shouldn't be uploaded, and returns the empty
+    /// ones which are uploaded anyway.
+    fn check_revisions(&self, to_upload: &[Commit]) -> Result<Vec<Commit>, CommandError> {
+        let mut empty_revisions = vec![];
+        for commit in to_upload {
+            if commit.is_empty(self.repo)? {
+                if !self.args.allow_empty {
+                    return Err(user_error_with_hint(
+                        format!(
+                            "Refusing to upload revision {} because it is empty",
+                            short_change_hash(commit.change_id())
+                        ),
+                        "Perhaps you squashed then ran upload? Maybe you meant to upload the \
+                         parent commit instead (eg. @-), or pass `--allow-empty` to upload it \
+                         anyway",
+                    ));
+                }
+                empty_revisions.push(commit.clone());
+            }
+            if commit.description().is_empty() {
+                return Err(user_error_with_hint(
+                    format!(
+                        "Refusing to upload revision {} because it is has no description",
+                        short_change_hash(commit.change_id())
+                    ),
+                    "Maybe you meant to upload the parent commit instead (eg. @-)",
+                ));
+            }
+        }
+        if !self.args.no_lint && self.settings.get_bool("gerrit.message-lint")? {
+            let mut problems = String::new();
+            for commit in to_upload {
+                for problem in lint_description(commit.description()) {
+                    writeln!(
+                        problems,
+                        "  {}: {problem}",
+                        short_change_hash(commit.change_id())
+                    )
+                    .unwrap();
+                }
+            }
+            if !problems.is_empty() {
+                return Err(user_error_with_hint(
+                    format!("Some descriptions don't pass `gerrit.message-lint`:\n{problems}")
+                        .trim_end(),
+                    "Fix the descriptions with `jj describe`, or pass `--no-lint` to upload \
+                     anyway",
+                ));
+            }
+        }
+        Ok(empty_revisions)
+    }
+
+    /// Returns whether uploading `revisions` revisions with `heads` heads
+    /// exceeds `gerrit.upload.confirm-threshold` or
+    /// `gerrit.upload.confirm-heads-threshold`.
+    fn exceeds_threshold(&self, revisions: usize, heads: usize) -> Result<bool, CommandError> {
+        let threshold: Option<usize> = self
+            .settings
+            .get("gerrit.upload.confirm-threshold")
+            .optional()?
+            .or(self.args.stack.then_some(STACK_CONFIRM_THRESHOLD));
+        let heads_threshold: Option<usize> = self
+            .settings
+            .get("gerrit.upload.confirm-heads-threshold")
+            .optional()?;
+        Ok(threshold.is_some_and(|threshold| revisions > threshold)
+            || heads_threshold.is_some_and(|threshold| heads > threshold))
+    }
+
+    fn upload_options(
+        &self,
+        rebase_on: Option<CommitId>,
+        push_options: Vec<String>,
+    ) -> Result<UploadOptions, CommandError> {
+        let args = self.args;
+        let settings = self.settings;
+        Ok(UploadOptions {
+            rebase_on,
+            trailers: args.trailers.clone(),
+            no_change_id: args.no_change_id,
+            change_id_trailer_key: settings.get_string("gerrit.change-id-trailer-key")?,
+            on_invalid_change_id: settings.get("gerrit.on-invalid-change-id")?,
+            change_id_from: settings.get("gerrit.change-id-from")?,
+            change_id_prefix: get_change_id_prefix(settings)?,
+            edit_change_id: args.edit_change_id,
+            update_change_id: args.update_change_id,
+            commit_hook_compatibility: settings.get_bool("gerrit.commit-hook-compatibility")?,
+            push_options,
+            show_diff_stat: args.show_diff_stat || args.dry_run,
+            dry_run: args.dry_run || args.dump_descriptions.is_some(),
+            quiet_remote: args.quiet_remote,
+            keep_going: args.keep_going,
+            draft: args.draft,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use clap::Parser as _;
+    use jj_lib::config::ConfigLayer;
+    use jj_lib::config::ConfigSource;
+    use jj_lib::config::StackedConfig;
+    use testutils::TestRepo;
+    use testutils::create_random_commit;
+
+    use super::*;
+
+    #[derive(clap::Parser)]
+    struct TestCli {
+        #[command(flatten)]
+        args: UploadArgs,
+    }
+
+    fn parse_args(args: &[&str]) -> UploadArgs {
+        TestCli::try_parse_from(["upload"].iter().chain(args))
+            .unwrap()
+            .args
+    }
+
+    fn settings_from_toml(text: &str) -> UserSettings {
+        let mut config = StackedConfig::with_defaults();
+        config.extend_layers(crate::config::default_config_layers());
+        config.add_layer(ConfigLayer::parse(ConfigSource::User, text).unwrap());
+        UserSettings::from_config(config).unwrap()
+    }
+
+    #[test]
+    fn test_upload_planner() {
+        let test_repo = TestRepo::init();
+        let mut tx = test_repo.repo.start_transaction();
+        let commit = create_random_commit(tx.repo_mut())
+            .set_description("subject\n")
+            .write()
+            .unwrap();
+        let settings = settings_from_toml(
+            r#"
+            gerrit.reviewers = ["alice@example.com"]
+            gerrit.change-id-trailer-key = "Gerrit-Id"
+            "#,
+        );
+        let repo_defaults = RepoDefaults {
+            branch: Some("main".to_owned()),
+            topic: None,
+            reviewers: vec!["alice@example.com".to_owned(), "bob".to_owned()],
+            ..Default::default()
+        };
+        let plan = |args: &[&str]| {
+            let args = parse_args(args);
+            UploadPlanner {
+                args: &args,
+                settings: &settings,
+                repo: tx.repo(),
+                remote: RemoteName::new("origin"),
+                repo_defaults: &repo_defaults,
+            }
+            .plan(slice::from_ref(&commit), slice::from_ref(commit.id()), None)
+        };
+
+        let upload_plan = plan(&["--wip", "--topic=a b", "--label=Verified+1"]).unwrap();
+        assert_eq!(
+            upload_plan.push_targets,
+            [(commit.id().clone(), "main".to_owned())]
+        );
+        assert_eq!(
+            upload_plan.options.push_options,
+            [
+                "wip",
+                "topic=a%20b",
+                "r=alice%40example%2Ecom",
+                "r=bob",
+                "l=Verified+1",
+            ]
+        );
+        assert_eq!(upload_plan.repo_reviewers, ["bob"]);
+        assert_eq!(upload_plan.options.change_id_trailer_key, "Gerrit-Id");
+        assert!(upload_plan.assumed_branch.is_none());
+        assert!(!upload_plan.exceeds_threshold);
+
+        let upload_plan = plan(&["--remote-branch=release", "--dry-run"]).unwrap();
+        assert_eq!(upload_plan.target_branches(), ["release"]);
+        assert!(upload_plan.options.dry_run);
+        assert!(upload_plan.options.show_diff_stat);
+
+        let err = plan(&["--submit"]).err().unwrap();
+        assert_eq!(
+            err.error.to_string(),
+            "Refusing to submit changes on upload"
+        );
+        let err = plan(&["--remote-branch=refs/for/main"]).err().unwrap();
+        assert_eq!(
+            err.error.to_string(),
+            "Target branch 'refs/for/main' is already a Gerrit magic ref"
+        );
+    }
+
+    #[test]
+    fn test_upload_planner_thresholds() {
+        let test_repo = TestRepo::init();
+        let settings = settings_from_toml(
+            r#"
+            gerrit.upload.confirm-threshold = 3
+            gerrit.upload.confirm-heads-threshold = 1
+            "#,
+        );
+        let repo_defaults = RepoDefaults::default();
+        let exceeds_threshold = |args: &[&str], revisions, heads| {
+            let args = parse_args(args);
+            UploadPlanner {
+                args: &args,
+                settings: &settings,
+                repo: test_repo.repo.as_ref(),
+                remote: RemoteName::new("origin"),
+                repo_defaults: &repo_defaults,
+            }
+            .exceeds_threshold(revisions, heads)
+            .unwrap()
+        };
+        assert!(!exceeds_threshold(&[], 3, 1));
+        assert!(exceeds_threshold(&[], 4, 1));
+        assert!(exceeds_threshold(&[], 2, 2));
+        assert!(exceeds_threshold(&["--stack"], 4, 1));
+
+        let settings = settings_from_toml("");
+        let exceeds_threshold = |args: &[&str], revisions| {
+            let args = parse_args(args);
+            UploadPlanner {
+                args: &args,
+                settings: &settings,
+                repo: test_repo.repo.as_ref(),
+                remote: RemoteName::new("origin"),
+                repo_defaults: &repo_defaults,
+            }
+            .exceeds_threshold(revisions, revisions)
+            .unwrap()
+        };
+        assert!(!exceeds_threshold(&[], 100));
+        assert!(!exceeds_threshold(&["--stack"], STACK_CONFIRM_THRESHOLD));
+        assert!(exceeds_threshold(&["--stack"], STACK_CONFIRM_THRESHOLD + 1));
+    }
+}
@@ -29,8 +29,8 @@ mod edit;
 mod evolog;
 mod file;
 mod fix;
-#[cfg(feature = "git")]
-mod gerrit;
+#[cfg(feature = "gerrit")]
+pub mod gerrit;
 #[cfg(feature = "git")]
 mod git;
 mod help;
@@ -75,6 +75,7 @@ use tracing::instrument;
 
 use crate::cli_util::Args;
 use crate::cli_util::CommandHelper;
+use crate::cli_util::RevsetFunctionFactory;
 use crate::command_error::CommandError;
 use crate::complete;
 use crate::ui::Ui;
@@ -115,7 +116,7 @@ enum Command {
     #[command(subcommand)]
     File(file::FileCommand),
     Fix(fix::FixArgs),
-    #[cfg(feature = "git")]
+    #[cfg(feature = "gerrit")]
     #[command(subcommand)]
     Gerrit(gerrit::GerritCommand),
     #[cfg(feature = "git")]
@@ -164,6 +165,22 @@ pub fn default_app() -> clap::Command {
     Command::augment_subcommands(Args::command())
 }
 
+/// Returns factories of the revset functions provided by built-in commands.
+pub fn default_revset_function_factories<'a>() -> Vec<(&'static str, RevsetFunctionFactory<'a>)> {
+    #[cfg_attr(not(feature = "gerrit"), expect(unused_mut))]
+    let mut factories: Vec<(&'static str, RevsetFunctionFactory<'a>)> = vec![];
+    #[cfg(feature = "gerrit")]
+    factories.push((
+        "gerrit_uploaded",
+        Box::new(|settings| {
+            std::sync::Arc::new(crate::gerrit_util::gerrit_uploaded(
+                settings.get_string("gerrit.change-id-trailer-key"),
+            ))
+        }),
+    ));
+    factories
+}
+
 #[instrument(skip_all)]
 pub fn run_command(ui: &mut Ui, command_helper: &CommandHelper) -> Result<(), CommandError> {
     let subcommand = Command::from_arg_matches(command_helper.matches()).unwrap();
@@ -185,7 +202,7 @@ pub fn run_command(ui: &mut Ui, command_helper: &CommandHelper) -> Result<(), Co
         Command::Evolog(args) => evolog::cmd_evolog(ui, command_helper, args),
         Command::File(args) => file::cmd_file(ui, command_helper, args),
         Command::Fix(args) => fix::cmd_fix(ui, command_helper, args),
-        #[cfg(feature = "git")]
+        #[cfg(feature = "gerrit")]
         Command::Gerrit(sub_args) => gerrit::cmd_gerrit(ui, command_helper, sub_args),
         #[cfg(feature = "git")]
         Command::Git(args) => git::cmd_git(ui, command_helper, args),
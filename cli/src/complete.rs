@@ -24,6 +24,10 @@ use itertools::Itertools as _;
 use jj_lib::config::ConfigNamePathBuf;
 use jj_lib::file_util::normalize_path;
 use jj_lib::file_util::slash_path;
+#[cfg(feature = "gerrit")]
+use jj_lib::repo::RepoLoader;
+#[cfg(feature = "gerrit")]
+use jj_lib::repo::StoreFactories;
 use jj_lib::settings::UserSettings;
 use jj_lib::workspace::DefaultWorkspaceLoaderFactory;
 use jj_lib::workspace::WorkspaceLoaderFactory as _;
@@ -272,6 +276,52 @@ pub fn git_remotes() -> Vec<CompletionCandidate> {
     })
 }
 
+/// Completes the branches of the Gerrit remote from the local remote-tracking
+/// bookmarks, without contacting the server.
+#[cfg(feature = "gerrit")]
+pub fn gerrit_remote_branches() -> Vec<CompletionCandidate> {
+    with_jj(|jj, settings| {
+        let cwd = std::env::current_dir()
+            .and_then(dunce::canonicalize)
+            .map_err(user_error)?;
+        let workspace_dir =
+            match parse::parse_flag(&["-R", "--repository"], std::env::args()).next() {
+                Some(repository) => cwd.join(repository),
+                None => find_workspace_dir(&cwd).to_owned(),
+            };
+        let workspace_loader = DefaultWorkspaceLoaderFactory
+            .create(&workspace_dir)
+            .map_err(user_error)?;
+        let repo_loader = RepoLoader::init_from_file_system(
+            settings,
+            workspace_loader.repo_path(),
+            &StoreFactories::default(),
+        )
+        .map_err(user_error)?;
+        let remote = parse::parse_flag(&["--remote"], std::env::args()).next();
+        // Resolve the remote like `jj gerrit upload` does, but don't complain
+        // if there is none.
+        let Ok(remote) =
+            crate::gerrit_util::calculate_remote(repo_loader.store(), settings, remote.as_deref())
+        else {
+            return Ok(vec![]);
+        };
+        let remote = serde_json::to_string(&remote.value).unwrap();
+        let output = jj
+            .build()
+            .arg("bookmark")
+            .arg("list")
+            .arg("--all-remotes")
+            .arg("--template")
+            .arg(format!(r#"if(remote == {remote}, name ++ "\n")"#))
+            .output()
+            .map_err(user_error)?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+
+        Ok(stdout.lines().map(CompletionCandidate::new).collect())
+    })
+}
+
 pub fn template_aliases() -> Vec<CompletionCandidate> {
     with_jj(|_, settings| {
         let Ok(template_aliases) = load_template_aliases(&Ui::null(), settings.config()) else {
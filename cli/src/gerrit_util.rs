@@ -0,0 +1,342 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Gerrit utilities shared by various commands.
+
+use std::borrow::Borrow as _;
+use std::env;
+use std::sync::Arc;
+
+use bstr::BStr;
+use itertools::Itertools as _;
+use jj_lib::commit::Commit;
+use jj_lib::config::ConfigGetError;
+use jj_lib::config::ConfigGetResultExt as _;
+use jj_lib::config::ConfigNamePathBuf;
+use jj_lib::config::ToConfigNamePath;
+use jj_lib::content_hash::blake2b_hash;
+use jj_lib::git;
+use jj_lib::hex_util::encode_hex;
+use jj_lib::object_id::ObjectId as _;
+use jj_lib::revset::FunctionCallNode;
+use jj_lib::revset::LoweringContext;
+use jj_lib::revset::RevsetDiagnostics;
+use jj_lib::revset::RevsetExpression;
+use jj_lib::revset::RevsetFilterExtension;
+use jj_lib::revset::RevsetFilterPredicate;
+use jj_lib::revset::RevsetParseError;
+use jj_lib::revset::UserRevsetExpression;
+use jj_lib::settings::UserSettings;
+use jj_lib::store::Store;
+use jj_lib::trailer::parse_description_trailers;
+
+use crate::command_error::CommandError;
+use crate::command_error::user_error;
+use crate::command_error::user_error_with_hint;
+
+/// Trailer key Gerrit uses to identify a change across patchsets.
+pub const CHANGE_ID_TRAILER_KEY: &str = "Change-Id";
+
+/// Returns true if `value` is a well-formed Gerrit Change-Id, i.e. `I`
+/// followed by 40 hexadecimal digits.
+pub fn is_valid_change_id(value: &str) -> bool {
+    value
+        .strip_prefix('I')
+        .is_some_and(|hex| hex.len() == 40 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+/// Returns true if the description carries a valid Change-Id trailer named
+/// `key`, see `gerrit.change-id-trailer-key`.
+pub fn has_valid_change_id(description: &str, key: &str) -> bool {
+    parse_description_trailers(description)
+        .iter()
+        .any(|trailer| trailer.key == key && is_valid_change_id(&trailer.value))
+}
+
+/// Reads the string config value `key`, expanding `${NAME}` references to
+/// environment variables.
+///
+/// Returns an error if a referenced environment variable is not set.
+pub fn get_expanded_string(
+    settings: &UserSettings,
+    key: impl ToConfigNamePath,
+) -> Result<Option<String>, CommandError> {
+    let key = key.into_name_path();
+    let key: &ConfigNamePathBuf = key.borrow();
+    let Some(value) = settings.get_string(key).optional()? else {
+        return Ok(None);
+    };
+    let mut expanded = String::with_capacity(value.len());
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("${") {
+        expanded.push_str(&rest[..start]);
+        let Some((name, tail)) = rest[start + 2..].split_once('}') else {
+            return Err(user_error(format!(
+                "Unterminated environment variable reference in `{key}`: {value}"
+            )));
+        };
+        let Ok(var) = env::var(name) else {
+            return Err(user_error(format!(
+                "Environment variable `{name}` referenced by `{key}` is not set"
+            )));
+        };
+        expanded.push_str(&var);
+        rest = tail;
+    }
+    expanded.push_str(rest);
+    Ok(Some(expanded))
+}
+
+/// A Gerrit setting along with a description of where it came from, e.g. for
+/// `jj gerrit upload --dry-run --verbose`.
+#[derive(Clone, Debug)]
+pub struct Resolved {
+    pub value: String,
+    pub source: String,
+}
+
+impl Resolved {
+    pub fn new(value: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            source: source.into(),
+        }
+    }
+}
+
+/// Describes the config layer that `key` was resolved from, e.g.
+/// ``"`gerrit.default-remote` in repo config"``.
+pub fn config_source(settings: &UserSettings, key: impl ToConfigNamePath) -> String {
+    let key = key.into_name_path();
+    let key: &ConfigNamePathBuf = key.borrow();
+    let layer = settings
+        .config()
+        .layers()
+        .iter()
+        .rev()
+        .find(|layer| matches!(layer.look_up_item(key), Ok(Some(_))));
+    match layer {
+        Some(layer) => format!("`{key}` in {} config", layer.source),
+        None => format!("`{key}`"),
+    }
+}
+
+/// Returns true if `remote` is a URL rather than a remote name, e.g.
+/// `https://host/project`, `host:path`, or an alias rewritten by
+/// `url.<base>.insteadOf` such as `gerrit:project`.
+///
+/// Remote names can't contain `:`, so they're never mistaken for URLs.
+pub fn is_remote_url(remote: &str) -> bool {
+    remote.contains(':') && gix::url::parse(remote.into()).is_ok()
+}
+
+/// Determine the Gerrit remote to push to or fetch from. The logic is:
+///
+/// 1. If the user specifies `--remote`, use that
+/// 2. If the user has 'gerrit.default-remote' configured, use that
+/// 3. If Git has a default push remote configured, use that
+/// 4. If there is a remote named 'gerrit', use that
+/// 5. Otherwise, bail out
+pub fn calculate_remote(
+    store: &Arc<Store>,
+    settings: &UserSettings,
+    remote: Option<&str>,
+) -> Result<Resolved, CommandError> {
+    let git_repo = git::get_git_repo(store).map_err(|err| {
+        user_error_with_hint(
+            err,
+            "`jj gerrit` commands only work in repos using the Git backend, which is recorded in \
+             `.jj/repo/store/type`. Create a Git-backed repo with `jj git init` or `jj git clone`",
+        )
+    })?;
+    let remotes = git_repo.remote_names();
+    // Besides remote names, accept URLs and aliases rewritten by
+    // `url.<base>.insteadOf`, which Git resolves when pushing or fetching.
+    let is_remote = |remote: &str| remotes.contains(BStr::new(remote)) || is_remote_url(remote);
+
+    // If --remote was provided, use that
+    if let Some(remote) = remote {
+        if is_remote(remote) {
+            return Ok(Resolved::new(remote, "--remote"));
+        }
+        return Err(user_error(format!(
+            "The remote '{remote}' (specified via `--remote`) does not exist",
+        )));
+    }
+
+    // If the Gerrit-specific config was set, use that
+    if let Some(remote) = get_expanded_string(settings, "gerrit.default-remote")? {
+        if is_remote(&remote) {
+            return Ok(Resolved::new(
+                remote,
+                config_source(settings, "gerrit.default-remote"),
+            ));
+        }
+        return Err(user_error(format!(
+            "The remote '{remote}' (configured via `gerrit.default-remote`) does not exist",
+        )));
+    }
+
+    // If a general push remote was configured, use that
+    if let Some(remote) = git_repo.remote_default_name(gix::remote::Direction::Push) {
+        return Ok(Resolved::new(
+            remote.to_string(),
+            "Git's default push remote",
+        ));
+    }
+
+    // If there is a Git remote called "gerrit", use that
+    if remotes.iter().any(|r| **r == "gerrit") {
+        return Ok(Resolved::new("gerrit", "the remote named 'gerrit'"));
+    }
+
+    // Otherwise error out
+    let hint = if remotes.is_empty() {
+        "This repository has no remotes. Add one with `jj git remote add`, then set \
+         `gerrit.default-remote` or pass `--remote`"
+            .to_owned()
+    } else {
+        format!(
+            "Available remotes: {}. Set `gerrit.default-remote` or pass `--remote` to choose one",
+            remotes.iter().join(", ")
+        )
+    };
+    Err(user_error_with_hint(
+        "No remote specified, and no 'gerrit' remote was found",
+        hint,
+    ))
+}
+
+/// What new `Change-Id` trailers are derived from, see `gerrit.change-id-from`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeIdSource {
+    /// The jj change id, which is stable across rewrites.
+    #[default]
+    JjChangeId,
+    /// The tree and description of the commit, so that changing either
+    /// results in a new Gerrit change.
+    CommitTree,
+}
+
+/// Number of hex digits padding the 32 digits of a jj change id to a
+/// Change-Id, see `gerrit.change-id-prefix`.
+const CHANGE_ID_PREFIX_LENGTH: usize = 8;
+
+/// Reads and validates `gerrit.change-id-prefix`.
+pub fn get_change_id_prefix(settings: &UserSettings) -> Result<String, CommandError> {
+    let change_id_prefix = settings.get_string("gerrit.change-id-prefix")?;
+    if change_id_prefix.len() != CHANGE_ID_PREFIX_LENGTH
+        || !change_id_prefix
+            .chars()
+            .all(|c| matches!(c, '0'..='9' | 'a'..='f'))
+    {
+        return Err(user_error_with_hint(
+            format!("Invalid `gerrit.change-id-prefix` '{change_id_prefix}'"),
+            format!(
+                "It must be {CHANGE_ID_PREFIX_LENGTH} lowercase hexadecimal characters, which pad \
+                 the jj change id to a 40-character Change-Id"
+            ),
+        ));
+    }
+    Ok(change_id_prefix)
+}
+
+/// Returns the Change-Id to add to `commit` if it doesn't have one.
+pub fn new_gerrit_change_id(commit: &Commit, source: ChangeIdSource, prefix: &str) -> String {
+    match source {
+        ChangeIdSource::JjChangeId => {
+            // Gerrit change id is 40 chars, jj change id is 32, so we need
+            // padding. To be consistent with `format_gerrit_change_id_trailer``,
+            // we pad with 6a6a6964 (hex of "jjid") by default.
+            format!("I{}{prefix}", commit.change_id().hex())
+        }
+        ChangeIdSource::CommitTree => {
+            let hash = blake2b_hash(&(commit.tree_ids().clone(), commit.description().to_owned()));
+            format!("I{}", encode_hex(&hash[..20]))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct GerritUploadedFilter {
+    key: String,
+}
+
+impl RevsetFilterExtension for GerritUploadedFilter {
+    fn matches_commit(&self, commit: &Commit) -> bool {
+        has_valid_change_id(commit.description(), &self.key)
+    }
+}
+
+/// Implements the `gerrit_uploaded()` revset function, which matches commits
+/// whose description contains a valid Change-Id trailer named `key`.
+///
+/// An invalid key is only reported when the function is used.
+pub fn gerrit_uploaded(
+    key: Result<String, ConfigGetError>,
+) -> impl Fn(
+    &mut RevsetDiagnostics,
+    &FunctionCallNode,
+    &LoweringContext,
+) -> Result<Arc<UserRevsetExpression>, RevsetParseError>
++ Send
++ Sync
++ 'static {
+    move |_diagnostics, function, _context| {
+        function.expect_no_arguments()?;
+        let key = key
+            .as_ref()
+            .map_err(|err| RevsetParseError::expression(err.to_string(), function.name_span))?;
+        Ok(RevsetExpression::filter(RevsetFilterPredicate::Extension(
+            Arc::new(GerritUploadedFilter { key: key.clone() }),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_change_id() {
+        assert!(is_valid_change_id(
+            "I0123456789abcdef0123456789abcdef6a6a6964"
+        ));
+        // Too short or too long
+        assert!(!is_valid_change_id("I0123456789abcdef"));
+        assert!(!is_valid_change_id(
+            "I0123456789abcdef0123456789abcdef6a6a69640"
+        ));
+        // Missing or lowercase prefix
+        assert!(!is_valid_change_id(
+            "00123456789abcdef0123456789abcdef6a6a6964"
+        ));
+        assert!(!is_valid_change_id(
+            "i0123456789abcdef0123456789abcdef6a6a6964"
+        ));
+        // Not hexadecimal
+        assert!(!is_valid_change_id(
+            "Ixxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx"
+        ));
+        // Gerrit accepts uppercase hexadecimal digits too
+        assert!(is_valid_change_id(
+            "I0123456789ABCDEF0123456789ABCDEF6A6A6964"
+        ));
+        // Non-ASCII characters
+        assert!(!is_valid_change_id(
+            "I0123456789abcdef0123456789abcdef6a6a69é"
+        ));
+    }
+}
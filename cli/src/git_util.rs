@@ -207,6 +207,52 @@ impl GitSidebandProgressMessageWriter {
 }
 
 pub fn with_remote_git_callbacks<T>(ui: &Ui, f: impl FnOnce(git::RemoteCallbacks<'_>) -> T) -> T {
+    with_remote_git_callbacks_impl(ui, true, |_| {}, f)
+}
+
+/// Like [`with_remote_git_callbacks()`], but also collects the sideband
+/// messages sent by the remote into `sideband_lines`, one entry per line
+/// without the `remote: ` prefix.
+///
+/// The messages are only printed as usual if `echo` is true.
+pub fn with_remote_git_callbacks_capturing_sideband<T>(
+    ui: &Ui,
+    sideband_lines: &mut Vec<String>,
+    echo: bool,
+    f: impl FnOnce(git::RemoteCallbacks<'_>) -> T,
+) -> T {
+    let mut line = Vec::new();
+    let result = with_remote_git_callbacks_impl(
+        ui,
+        echo,
+        |message| {
+            for chunk in message.split_inclusive(|&c| c == b'\r' || c == b'\n') {
+                match chunk.split_last() {
+                    Some((b'\r' | b'\n', body)) => {
+                        line.extend_from_slice(body);
+                        if !line.is_empty() {
+                            sideband_lines.push(String::from_utf8_lossy(&line).into_owned());
+                        }
+                        line.clear();
+                    }
+                    _ => line.extend_from_slice(chunk),
+                }
+            }
+        },
+        f,
+    );
+    if !line.is_empty() {
+        sideband_lines.push(String::from_utf8_lossy(&line).into_owned());
+    }
+    result
+}
+
+fn with_remote_git_callbacks_impl<T>(
+    ui: &Ui,
+    echo_sideband: bool,
+    mut on_sideband_message: impl FnMut(&[u8]),
+    f: impl FnOnce(git::RemoteCallbacks<'_>) -> T,
+) -> T {
     let mut callbacks = git::RemoteCallbacks::default();
 
     let mut progress_callback;
@@ -220,7 +266,10 @@ pub fn with_remote_git_callbacks<T>(ui: &Ui, f: impl FnOnce(git::RemoteCallbacks
 
     let mut sideband_progress_writer = GitSidebandProgressMessageWriter::new(ui);
     let mut sideband_progress_callback = |progress_message: &[u8]| {
-        sideband_progress_writer.write(ui, progress_message).ok();
+        on_sideband_message(progress_message);
+        if echo_sideband {
+            sideband_progress_writer.write(ui, progress_message).ok();
+        }
     };
     callbacks.sideband_progress = Some(&mut sideband_progress_callback);
 
@@ -26,6 +26,8 @@ pub mod description_util;
 pub mod diff_util;
 pub mod formatter;
 pub mod generic_templater;
+#[cfg(feature = "gerrit")]
+pub mod gerrit_util;
 #[cfg(feature = "git")]
 pub mod git_util;
 #[cfg(not(feature = "git"))]
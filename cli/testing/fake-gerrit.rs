@@ -0,0 +1,169 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A fake Gerrit server, installed as the `pre-receive` and `post-receive`
+//! hooks of a Git repository.
+//!
+//! Pushes to `refs/for/<branch>[%<options>]` are recorded in the
+//! `fake-gerrit/pushes` file of the Git directory, one line per pushed ref:
+//! `<commit id> <branch> [<option>,...]`. Pushes to `refs/drafts/<branch>` are
+//! recorded the same way, with a `draft` option in front of the pushed ones.
+//! Each push is also recorded in the `fake-gerrit/receives` file, as a line
+//! listing the pushed branches. Each pushed commit is assigned a change number
+//! based on its `Change-Id` footer, and Gerrit-like sideband messages listing
+//! the change URLs are printed. The footer key can be changed by writing it to
+//! the `fake-gerrit/change-id-key` file. The `refs/for/` and `refs/drafts/`
+//! refs are deleted again after the push, so the same branch can be pushed to
+//! repeatedly.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::BufRead as _;
+use std::io::Write as _;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::exit;
+
+use clap::Parser;
+use itertools::Itertools as _;
+
+/// A fake Gerrit server, useful for testing
+#[derive(Parser, Debug)]
+#[clap()]
+enum Args {
+    /// Record pushes to `refs/for/` and `refs/drafts/`, and report the created
+    /// changes
+    PreReceive,
+    /// Delete the pushed `refs/for/` and `refs/drafts/` refs
+    PostReceive,
+}
+
+const REVIEW_URL: &str = "https://gerrit.example.com/c/repo/+";
+
+fn main() {
+    let args: Args = Args::parse();
+    let git_dir = PathBuf::from(std::env::var_os("GIT_DIR").unwrap_or(".".into()));
+    let state_dir = git_dir.join("fake-gerrit");
+    fs::create_dir_all(&state_dir).unwrap();
+
+    let mut branches = vec![];
+    for line in std::io::stdin().lock().lines() {
+        let line = line.unwrap();
+        let (_old, new, ref_name) = line.split(' ').collect_tuple().unwrap();
+        let (target, draft) = if let Some(target) = ref_name.strip_prefix("refs/for/") {
+            (target, false)
+        } else if let Some(target) = ref_name.strip_prefix("refs/drafts/") {
+            (target, true)
+        } else {
+            continue;
+        };
+        match args {
+            Args::PreReceive => {
+                receive(&state_dir, new, target, draft);
+                branches.push(target.split('%').next().unwrap().to_owned());
+            }
+            Args::PostReceive => {
+                git(&["update-ref", "-d", ref_name]);
+            }
+        }
+    }
+    if !branches.is_empty() {
+        let mut receives = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(state_dir.join("receives"))
+            .unwrap();
+        writeln!(receives, "{}", branches.join(" ")).unwrap();
+    }
+}
+
+fn receive(state_dir: &Path, new: &str, target: &str, draft: bool) {
+    let (branch, options) = target.split_once('%').unwrap_or((target, ""));
+    let options = match (draft, options) {
+        (false, options) => options.to_owned(),
+        (true, "") => "draft".to_owned(),
+        (true, options) => format!("draft,{options}"),
+    };
+    let mut pushes = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(state_dir.join("pushes"))
+        .unwrap();
+    if options.is_empty() {
+        writeln!(pushes, "{new} {branch}").unwrap();
+    } else {
+        writeln!(pushes, "{new} {branch} {options}").unwrap();
+    }
+
+    let change_id_key = fs::read_to_string(state_dir.join("change-id-key"))
+        .map(|key| key.trim().to_owned())
+        .unwrap_or_else(|_| "Change-Id".to_owned());
+    let changes_path = state_dir.join("changes");
+    let mut changes = fs::read_to_string(&changes_path).unwrap_or_default();
+    let commits = git(&["rev-list", "--reverse", new, "--not", "--branches"]);
+    let mut messages = vec![];
+    for commit in commits.lines() {
+        let change_id = git(&[
+            "log",
+            "-1",
+            &format!("--format=%(trailers:key={change_id_key},valueonly,separator=%x2C)"),
+            commit,
+        ]);
+        let change_id = change_id.trim();
+        if change_id.is_empty() {
+            eprintln!(
+                "ERROR: commit {}: missing {change_id_key} in message footer",
+                &commit[..7]
+            );
+            exit(1);
+        }
+        let subject = git(&["log", "-1", "--format=%s", commit]);
+        let known_number = changes.lines().find_map(|line| {
+            let (id, number) = line.split_once(' ')?;
+            (id == change_id).then(|| number.to_owned())
+        });
+        let (number, status) = match known_number {
+            Some(number) => (number, ""),
+            None => {
+                let number = (changes.lines().count() + 1).to_string();
+                writeln!(changes, "{change_id} {number}").unwrap();
+                (number, " [NEW]")
+            }
+        };
+        messages.push(format!(
+            "  {REVIEW_URL}/{number} {}{status}",
+            subject.trim()
+        ));
+    }
+    fs::write(&changes_path, changes).unwrap();
+
+    eprintln!();
+    eprintln!("SUCCESS");
+    eprintln!();
+    for message in messages {
+        eprintln!("{message}");
+    }
+    eprintln!();
+}
+
+fn git(args: &[&str]) -> String {
+    let output = Command::new("git").args(args).output().unwrap();
+    assert!(
+        output.status.success(),
+        "git {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
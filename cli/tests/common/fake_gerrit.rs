@@ -0,0 +1,145 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use super::TestEnvironment;
+use super::TestWorkDir;
+use super::create_commit;
+use super::create_commit_with_files;
+use super::fake_gerrit_path;
+
+/// A colocated repository acting as a Gerrit server.
+///
+/// See `testing/fake-gerrit.rs` for what the fake server does on push.
+pub struct FakeGerrit {
+    git_dir: PathBuf,
+}
+
+impl FakeGerrit {
+    /// Installs the fake Gerrit hooks in the colocated repository at
+    /// `work_dir`.
+    pub fn install(work_dir: &TestWorkDir) -> Self {
+        let git_dir = work_dir.root().join(".git");
+        let hooks_dir = git_dir.join("hooks");
+        std::fs::create_dir_all(&hooks_dir).unwrap();
+        let fake_gerrit_path = fake_gerrit_path().replace('\\', "/");
+        for hook in ["pre-receive", "post-receive"] {
+            let hook_path = hooks_dir.join(hook);
+            std::fs::write(
+                &hook_path,
+                format!("#!/bin/sh\nexec '{fake_gerrit_path}' {hook}\n"),
+            )
+            .unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt as _;
+
+                std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o700))
+                    .unwrap();
+            }
+        }
+        Self { git_dir }
+    }
+
+    /// Makes the server look up the Change-Id in footers named `key` instead of
+    /// `Change-Id`.
+    pub fn set_change_id_key(&self, key: &str) {
+        let state_dir = self.git_dir.join("fake-gerrit");
+        std::fs::create_dir_all(&state_dir).unwrap();
+        std::fs::write(state_dir.join("change-id-key"), key).unwrap();
+    }
+
+    /// Returns the recorded pushes, one `<commit id> <branch> [<options>]`
+    /// line per pushed ref.
+    pub fn pushes(&self) -> String {
+        std::fs::read_to_string(self.git_dir.join("fake-gerrit").join("pushes")).unwrap_or_default()
+    }
+
+    /// Returns the recorded pushes, one line listing the pushed branches per
+    /// `git push`.
+    pub fn receives(&self) -> String {
+        std::fs::read_to_string(self.git_dir.join("fake-gerrit").join("receives"))
+            .unwrap_or_default()
+    }
+}
+
+/// Creates a colocated `remote` repo with a single commit `a`, and clones it
+/// into `local`. Returns the remote and local workspaces.
+pub fn set_up_remote_and_clone(test_env: &TestEnvironment) -> (TestWorkDir<'_>, TestWorkDir<'_>) {
+    set_up_remote_and_clone_with(test_env, &["a"], &[])
+}
+
+/// Like [`set_up_remote_and_clone()`], but creates a commit and bookmark for
+/// each of `branches` in the remote. The first branch is a root commit with
+/// `files` added to it, e.g. a `.gerrit.toml`, and the others are based on it.
+pub fn set_up_remote_and_clone_with<'a>(
+    test_env: &'a TestEnvironment,
+    branches: &[&str],
+    files: &[(&str, &str)],
+) -> (TestWorkDir<'a>, TestWorkDir<'a>) {
+    let (base, others) = branches.split_first().unwrap();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    let base_content = format!("{base}\n");
+    let files = [&[(*base, base_content.as_str())], files].concat();
+    create_commit_with_files(&remote_dir, base, &[], &files);
+    for branch in others {
+        create_commit(&remote_dir, branch, &[base]);
+    }
+
+    test_env
+        .run_jj_in(".", ["git", "clone", "remote", "local"])
+        .success();
+    (remote_dir, test_env.work_dir("local"))
+}
+
+/// Like [`set_up_remote_and_clone()`], but also installs the fake Gerrit
+/// server in the remote. Returns the server and the local workspace.
+pub fn set_up_fake_gerrit(test_env: &TestEnvironment) -> (FakeGerrit, TestWorkDir<'_>) {
+    let (remote_dir, local_dir) = set_up_remote_and_clone(test_env);
+    (FakeGerrit::install(&remote_dir), local_dir)
+}
+
+/// Returns insta settings that strip the padding the sideband writer appends
+/// to each remote line.
+pub fn sideband_insta_settings() -> insta::Settings {
+    let mut settings = insta::Settings::clone_current();
+    settings.add_filter(r"\s*\n", "\n");
+    settings
+}
+
+/// Points the Gerrit ref of a patchset at the `target` revision of the remote
+/// repo.
+pub fn create_patchset_ref(remote_dir: &TestWorkDir, change: u64, patchset: u64, target: &str) {
+    let commit_id = remote_dir
+        .run_jj(["log", "--no-graph", "-r", target, "-T", "commit_id"])
+        .success()
+        .stdout
+        .into_raw();
+    let ref_name = format!("refs/changes/{:02}/{change}/{patchset}", change % 100);
+    remote_dir
+        .run_jj([
+            "util",
+            "exec",
+            "--",
+            "git",
+            "update-ref",
+            &ref_name,
+            &commit_id,
+        ])
+        .success();
+}
@@ -14,10 +14,24 @@
 
 mod command_output;
 mod config_schema_defaults;
+#[cfg(feature = "gerrit")]
+mod fake_gerrit;
 mod test_environment;
 
 pub use self::command_output::CommandOutput;
 pub use self::config_schema_defaults::default_config_from_schema;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::FakeGerrit;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::create_patchset_ref;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::set_up_fake_gerrit;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::set_up_remote_and_clone;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::set_up_remote_and_clone_with;
+#[cfg(feature = "gerrit")]
+pub use self::fake_gerrit::sideband_insta_settings;
 pub use self::test_environment::TestEnvironment;
 pub use self::test_environment::TestWorkDir;
 
@@ -33,6 +47,13 @@ pub fn fake_editor_path() -> String {
     path.as_os_str().to_str().unwrap().to_owned()
 }
 
+#[cfg(feature = "gerrit")]
+pub fn fake_gerrit_path() -> String {
+    let path = assert_cmd::cargo::cargo_bin!("fake-gerrit");
+    assert!(path.is_file());
+    path.as_os_str().to_str().unwrap().to_owned()
+}
+
 pub fn fake_diff_editor_path() -> String {
     let path = assert_cmd::cargo::cargo_bin!("fake-diff-editor");
     assert!(path.is_file());
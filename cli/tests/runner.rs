@@ -39,6 +39,13 @@ mod test_file_show_command;
 mod test_file_track_untrack_commands;
 mod test_fix_command;
 mod test_generate_md_cli_help;
+#[cfg(feature = "gerrit")]
+mod test_gerrit_download;
+#[cfg(feature = "gerrit")]
+mod test_gerrit_fetch;
+#[cfg(feature = "gerrit")]
+mod test_gerrit_status;
+#[cfg(feature = "gerrit")]
 mod test_gerrit_upload;
 mod test_git_clone;
 mod test_git_colocated;
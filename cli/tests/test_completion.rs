@@ -752,6 +752,67 @@ fn test_remote_names() {
     ");
 }
 
+#[cfg(feature = "gerrit")]
+#[test]
+fn test_gerrit_remote_branches() {
+    let test_env = TestEnvironment::default();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    for bookmark in ["main", "release/1.0", "other"] {
+        remote_dir
+            .run_jj(["bookmark", "create", "-r@", bookmark])
+            .success();
+    }
+    test_env
+        .run_jj_in(".", ["git", "clone", "remote", "local"])
+        .success();
+    let local_dir = test_env.work_dir("local");
+    local_dir
+        .run_jj(["bookmark", "create", "-r@", "local-only"])
+        .success();
+
+    // Like `jj gerrit upload`, Git's default push remote is used by default
+    let output = local_dir.complete_fish(["gerrit", "upload", "--remote-branch", ""]);
+    insta::assert_snapshot!(output, @"
+    main
+    other
+    release/1.0
+    [EOF]
+    ");
+
+    let output =
+        local_dir.complete_fish(["gerrit", "upload", "--remote=origin", "--remote-branch", ""]);
+    insta::assert_snapshot!(output, @"
+    main
+    other
+    release/1.0
+    [EOF]
+    ");
+
+    // Nothing is completed if the remote doesn't exist
+    let output = local_dir.complete_fish([
+        "gerrit",
+        "upload",
+        "--remote=missing",
+        "--remote-branch",
+        "",
+    ]);
+    insta::assert_snapshot!(output, @"");
+
+    test_env.add_config("gerrit.default-remote = 'missing'");
+    let output = local_dir.complete_fish(["gerrit", "upload", "-b", "r"]);
+    insta::assert_snapshot!(output, @"");
+
+    test_env.add_config("gerrit.default-remote = 'origin'");
+    let output = local_dir.complete_fish(["gerrit", "upload", "-b", "r"]);
+    insta::assert_snapshot!(output, @"
+    release/1.0
+    [EOF]
+    ");
+}
+
 #[test_case(Shell::Bash; "bash")]
 #[test_case(Shell::Zsh; "zsh")]
 #[test_case(Shell::Fish; "fish")]
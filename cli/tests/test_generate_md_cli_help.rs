@@ -22,6 +22,10 @@ const PREAMBLE: &str = r#"
 "#;
 
 #[test]
+#[cfg_attr(
+    not(feature = "gerrit"),
+    ignore = "the reference includes the `jj gerrit` commands"
+)]
 fn test_generate_markdown_docs_in_docs_dir() {
     let test_env = TestEnvironment::default();
     let mut markdown_help = PREAMBLE.to_string();
@@ -0,0 +1,138 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+use crate::common::create_commit;
+use crate::common::create_patchset_ref;
+use crate::common::set_up_remote_and_clone;
+
+#[test]
+fn test_gerrit_download() {
+    let test_env = TestEnvironment::default();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    create_commit(&remote_dir, "a", &[]);
+    // The patchsets aren't reachable from any branch
+    remote_dir.run_jj(["new", "a", "-m", "b"]).success();
+    create_patchset_ref(&remote_dir, 1, 1, "@");
+    remote_dir.run_jj(["describe", "-m", "b v2"]).success();
+    create_patchset_ref(&remote_dir, 1, 2, "@");
+    // Refs other than patchsets are ignored
+    remote_dir
+        .run_jj([
+            "util",
+            "exec",
+            "--",
+            "git",
+            "update-ref",
+            "refs/changes/01/1/meta",
+            "a",
+        ])
+        .success();
+
+    test_env
+        .run_jj_in(".", ["git", "clone", "remote", "local"])
+        .success();
+    let local_dir = test_env.work_dir("local");
+
+    // The latest patchset is downloaded by default
+    let output = local_dir.run_jj(["gerrit", "download", "1", "--remote=origin"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Downloaded patchset 2 of change 1 as zsuskuln fbeb49bf (empty) b v2
+    Working copy  (@) now at: kmkuslsw 71991444 (empty) (no description set)
+    Parent commit (@-)      : zsuskuln fbeb49bf (empty) b v2
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "all()"]);
+    insta::assert_snapshot!(output, @"
+    @  kmkuslsw test.user@example.com 2001-02-03 08:05:18 71991444
+    │  (empty) (no description set)
+    ○  zsuskuln test.user@example.com 2001-02-03 08:05:13 fbeb49bf
+    │  (empty) b v2
+    ◆  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 a 7d980be7
+    │  a
+    ◆  zzzzzzzz root() 00000000
+    [EOF]
+    ");
+
+    // A specific patchset can be downloaded, using the default remote
+    test_env.add_config("gerrit.default-remote = 'origin'");
+    let output = local_dir.run_jj(["gerrit", "download", "1/1"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Downloaded patchset 1 of change 1 as zsuskuln/0 58aaf278 (divergent) (empty) b
+    Working copy  (@) now at: lylxulpl 38bb2bf6 (empty) (no description set)
+    Parent commit (@-)      : zsuskuln/0 58aaf278 (divergent) (empty) b
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "all()"]);
+    insta::assert_snapshot!(output, @"
+    @  lylxulpl test.user@example.com 2001-02-03 08:05:20 38bb2bf6
+    │  (empty) (no description set)
+    ○  zsuskuln/0 test.user@example.com 2001-02-03 08:05:10 58aaf278 (divergent)
+    │  (empty) b
+    │ ○  zsuskuln/1 test.user@example.com 2001-02-03 08:05:13 fbeb49bf (divergent)
+    ├─╯  (empty) b v2
+    ◆  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 a 7d980be7
+    │  a
+    ◆  zzzzzzzz root() 00000000
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_download_missing_change() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_patchset_ref(&remote_dir, 1, 1, "a");
+    let output = local_dir.run_jj(["gerrit", "download", "2", "--remote=origin"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Change 2 was not found on remote 'origin'
+    Hint: Check the change number, and that you have access to the change
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir.run_jj(["gerrit", "download", "1/2", "--remote=origin"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Patchset 2 of change 1 was not found on remote 'origin'
+    Hint: Check the change number, and that you have access to the change
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir.run_jj(["gerrit", "download", "1/x", "--remote=origin"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: invalid value '1/x' for '<CHANGE[/PATCHSET]>': Expected CHANGE or CHANGE/PATCHSET, got '1/x'
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+
+    // Nothing was imported
+    let output = local_dir.run_jj(["log", "-r", "all()"]);
+    insta::assert_snapshot!(output, @"
+    @  zsuskuln test.user@example.com 2001-02-03 08:05:10 c2934cfb
+    │  (empty) (no description set)
+    │ ◆  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 a@origin 7d980be7
+    ├─╯  a
+    ◆  zzzzzzzz root() 00000000
+    [EOF]
+    ");
+}
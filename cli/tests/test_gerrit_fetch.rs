@@ -0,0 +1,115 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+use crate::common::create_commit;
+use crate::common::create_patchset_ref;
+use crate::common::set_up_remote_and_clone;
+
+#[test]
+fn test_gerrit_fetch() {
+    let test_env = TestEnvironment::default();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    create_commit(&remote_dir, "a", &[]);
+    // Change 2 is stacked on top of change 1, and change 3 is independent
+    remote_dir.run_jj(["new", "a", "-m", "b"]).success();
+    create_patchset_ref(&remote_dir, 1, 1, "@");
+    remote_dir.run_jj(["new", "-m", "c"]).success();
+    create_patchset_ref(&remote_dir, 2, 1, "@");
+    remote_dir.run_jj(["describe", "-m", "c v2"]).success();
+    create_patchset_ref(&remote_dir, 2, 2, "@");
+    remote_dir.run_jj(["new", "a", "-m", "d"]).success();
+    create_patchset_ref(&remote_dir, 3, 1, "@");
+
+    test_env
+        .run_jj_in(".", ["git", "clone", "remote", "local"])
+        .success();
+    let local_dir = test_env.work_dir("local");
+
+    // Shared ancestors are only added once, and the working copy stays put
+    let output = local_dir.run_jj([
+        "gerrit",
+        "fetch",
+        "--remote=origin",
+        "--change=1",
+        "--change=2",
+        "--change=2/1",
+        "--change=3",
+        "--change=3",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Fetched patchset 1 of change 1 as zsuskuln 58aaf278 (empty) b
+    Fetched patchset 2 of change 2 as yqosqzyt/1 63fad87a (divergent) (empty) c v2
+    Fetched patchset 1 of change 2 as yqosqzyt/0 c76014c4 (divergent) (empty) c
+    Fetched patchset 1 of change 3 as wqnwkozp 09e4280a (empty) d
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "all()"]);
+    insta::assert_snapshot!(output, @"
+    @  ruktrxxu test.user@example.com 2001-02-03 08:05:22 fb9e625c
+    │  (empty) (no description set)
+    │ ○  wqnwkozp test.user@example.com 2001-02-03 08:05:19 09e4280a
+    ├─╯  (empty) d
+    │ ○  yqosqzyt/0 test.user@example.com 2001-02-03 08:05:13 c76014c4 (divergent)
+    │ │  (empty) c
+    │ │ ○  yqosqzyt/1 test.user@example.com 2001-02-03 08:05:16 63fad87a (divergent)
+    │ ├─╯  (empty) c v2
+    │ ○  zsuskuln test.user@example.com 2001-02-03 08:05:10 58aaf278
+    ├─╯  (empty) b
+    ◆  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 a 7d980be7
+    │  a
+    ◆  zzzzzzzz root() 00000000
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_fetch_missing_changes() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_patchset_ref(&remote_dir, 1, 1, "a");
+    test_env.add_config("gerrit.default-remote = 'origin'");
+
+    let output = local_dir.run_jj(["gerrit", "fetch"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the following required arguments were not provided:
+      --change <CHANGE[/PATCHSET]>
+
+    Usage: jj gerrit fetch --change <CHANGE[/PATCHSET]>
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+    let output = local_dir.run_jj(["gerrit", "fetch", "-c1", "-c2", "-c1/3"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Changes 2, 1/3 were not found on remote 'origin'
+    Hint: Check the change numbers, and that you have access to the changes
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir.run_jj(["op", "log", "-n1"]);
+    insta::assert_snapshot!(output, @"
+    @  9b10e3e12a2c test-username@host.example.com 2001-02-03 04:05:10.000 +07:00 - 2001-02-03 04:05:10.000 +07:00
+    │  fetch from git remote into empty repo
+    │  args: jj git clone remote local
+    [EOF]
+    ");
+}
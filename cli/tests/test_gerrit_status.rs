@@ -0,0 +1,98 @@
+// Copyright 2025 The Jujutsu Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+#[test]
+fn test_gerrit_status() {
+    let test_env = TestEnvironment::default();
+    test_env.run_jj_in(".", ["git", "init", "repo"]).success();
+    let work_dir = test_env.work_dir("repo");
+    work_dir
+        .run_jj([
+            "describe",
+            "-m",
+            "a\n\nChange-Id: I1111111111111111111111111111111111111111",
+        ])
+        .success();
+    work_dir.run_jj(["new", "-m", "b"]).success();
+    work_dir
+        .run_jj(["new", "-m", "c\n\nChange-Id: Ibroken"])
+        .success();
+    work_dir
+        .run_jj([
+            "new",
+            "-m",
+            "d\n\nChange-Id: I2222222222222222222222222222222222222222\nChange-Id: \
+             I3333333333333333333333333333333333333333",
+        ])
+        .success();
+    work_dir.run_jj(["new"]).success();
+
+    // The mutable ancestors of @ are shown by default
+    let output = work_dir.run_jj(["gerrit", "status"]);
+    insta::assert_snapshot!(output, @"
+    81dd11afd48c missing  I8b12d1f268f89aab6c84562e484912d16a6a6964
+    03c014c06c50 multiple I2222222222222222222222222222222222222222, I3333333333333333333333333333333333333333
+    b4f713b8026f invalid  Ibroken
+    95d83003b915 missing  Iffdaa62087a280bddc5e3d3ff933b8ae6a6a6964
+    351eb868c10d valid    I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+
+    let output = work_dir.run_jj(["gerrit", "status", "-r", "@----", "-r", "@-"]);
+    insta::assert_snapshot!(output, @"
+    03c014c06c50 multiple I2222222222222222222222222222222222222222, I3333333333333333333333333333333333333333
+    351eb868c10d valid    I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+
+    // With --stat, the revisions in each state are counted
+    let output = work_dir.run_jj(["gerrit", "status", "--stat"]);
+    insta::assert_snapshot!(output, @"
+    valid    1
+    missing  2
+    invalid  1
+    multiple 1
+    [EOF]
+    ");
+    let output = work_dir.run_jj(["gerrit", "status", "--stat", "-r", "none()"]);
+    insta::assert_snapshot!(output, @"
+    valid    0
+    missing  0
+    invalid  0
+    multiple 0
+    [EOF]
+    ");
+
+    // The footer key and derived Change-Ids follow the upload config
+    test_env.add_config(r#"gerrit.change-id-trailer-key = "Link""#);
+    test_env.add_config(r#"gerrit.change-id-prefix = "00000000""#);
+    let output = work_dir.run_jj(["gerrit", "status", "-r", "@----::@---"]);
+    insta::assert_snapshot!(output, @"
+    95d83003b915 missing  Iffdaa62087a280bddc5e3d3ff933b8ae00000000
+    351eb868c10d missing  I9a45c67d3e96a7e5007c110ede34dec500000000
+    [EOF]
+    ");
+
+    // Change-Ids derived from the commit would change on rewrite, so they
+    // aren't shown
+    test_env.add_config(r#"gerrit.change-id-from = "commit-tree""#);
+    let output = work_dir.run_jj(["gerrit", "status", "-r", "@----::@---"]);
+    insta::assert_snapshot!(output, @"
+    95d83003b915 missing  -
+    351eb868c10d missing  -
+    [EOF]
+    ");
+}
@@ -12,9 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt::Write as _;
+
+use itertools::Itertools as _;
+
+use crate::common::FakeGerrit;
 use crate::common::TestEnvironment;
 use crate::common::create_commit;
 use crate::common::create_commit_with_files;
+use crate::common::force_interactive;
+use crate::common::set_up_fake_gerrit;
+use crate::common::set_up_remote_and_clone;
+use crate::common::set_up_remote_and_clone_with;
+use crate::common::sideband_insta_settings;
 
 #[test]
 fn test_gerrit_upload_dryrun() {
@@ -29,6 +39,7 @@ fn test_gerrit_upload_dryrun() {
     insta::assert_snapshot!(output, @r"
     ------- stderr -------
     Error: No remote specified, and no 'gerrit' remote was found
+    Hint: This repository has no remotes. Add one with `jj git remote add`, then set `gerrit.default-remote` or pass `--remote`
     [EOF]
     [exit status: 1]
     ");
@@ -73,6 +84,9 @@ fn test_gerrit_upload_dryrun() {
     ------- stderr -------
     Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
     Dry-run: Would push zsuskuln 123b4d91 b | b
+    a | 1 +
+    b | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
     [EOF]
     ");
 
@@ -81,7 +95,119 @@ fn test_gerrit_upload_dryrun() {
     ------- stderr -------
     Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
     Dry-run: Would push zsuskuln 123b4d91 b | b
+    a | 1 +
+    b | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_no_matching_remote() {
+    let test_env = TestEnvironment::default();
+    test_env.run_jj_in(".", ["git", "init", "repo"]).success();
+    let work_dir = test_env.work_dir("repo");
+    create_commit(&work_dir, "a", &[]);
+    work_dir
+        .run_jj(["git", "remote", "add", "foo", "http://example.com/repo/foo"])
+        .success();
+    work_dir
+        .run_jj(["git", "remote", "add", "bar", "http://example.com/repo/bar"])
+        .success();
+
+    let output = work_dir.run_jj(["gerrit", "upload", "-r", "a", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No remote specified, and no 'gerrit' remote was found
+    Hint: Available remotes: bar, foo. Set `gerrit.default-remote` or pass `--remote` to choose one
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_url_alias_remote() {
+    let test_env = TestEnvironment::default();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    create_commit(&remote_dir, "a", &[]);
+
+    test_env
+        .run_jj_in(".", ["git", "clone", "--colocate", "remote", "local"])
+        .success();
+    let local_dir = test_env.work_dir("local");
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+    let base_url = format!("{}/", test_env.env_root().display());
+    local_dir
+        .run_jj([
+            "util",
+            "exec",
+            "--",
+            "git",
+            "config",
+            &format!("url.{base_url}.insteadOf"),
+            "gerrit:",
+        ])
+        .success();
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote=gerrit:remote",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'gerrit:remote'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+
+    test_env.add_config("gerrit.default-remote = 'gerrit:remote'");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=other"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'gerrit:remote'), target branch 'other'
+    Pushing yqosqzyt cfd7d90d c | c
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname) %(subject)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main b
+    refs/for/other c
+    [EOF]
+    ");
+
+    // Unknown names without `:` are still rejected
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote=gerrit"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The remote 'gerrit' (specified via `--remote`) does not exist
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // So are values with `:` that aren't URLs
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote=gerrit:"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The remote 'gerrit:' (specified via `--remote`) does not exist
     [EOF]
+    [exit status: 1]
     ");
 }
 
@@ -117,7 +243,7 @@ fn test_gerrit_upload_failure() {
     insta::assert_snapshot!(output, @"
     ------- stderr -------
     Error: Refusing to upload revision mzvwutvlkqwt because it is empty
-    Hint: Perhaps you squashed then ran upload? Maybe you meant to upload the parent commit instead (eg. @-)
+    Hint: Perhaps you squashed then ran upload? Maybe you meant to upload the parent commit instead (eg. @-), or pass `--allow-empty` to upload it anyway
     [EOF]
     [exit status: 1]
     ");
@@ -148,6 +274,49 @@ fn test_gerrit_upload_failure() {
     ");
 }
 
+#[test]
+fn test_gerrit_upload_allow_empty() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit_with_files(&local_dir, "b", &["a@origin"], &[]);
+
+    // Empty revisions are refused by default
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload revision mzvwutvlkqwt because it is empty
+    Hint: Perhaps you squashed then ran upload? Maybe you meant to upload the parent commit instead (eg. @-), or pass `--allow-empty` to upload it anyway
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // They are uploaded with a warning with --allow-empty
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--allow-empty",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: Uploading revision mzvwutvlkqwt even though it is empty
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl ece15efa b | (empty) b
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"aa17bee9469e50e7222bfa439e0c13cd7a2c63a4 main");
+}
+
 #[test]
 fn test_gerrit_upload_local_implicit_change_ids() {
     let test_env = TestEnvironment::default();
@@ -302,6 +471,7 @@ fn test_gerrit_upload_local_explicit_change_ids() {
     let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"]);
     insta::assert_snapshot!(output, @r"
     ------- stderr -------
+    All revisions to upload already have Change-Id trailers, so none are added
     Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
     Pushing vruxwmqv 27c0bdd0 c | c
     [EOF]
@@ -503,64 +673,4103 @@ fn test_gerrit_upload_bad_change_ids() {
     Pushing yostqsxw 7252a52a bb
     [EOF]
     ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-rbb",
+        "--remote-branch=other",
+        "--config=gerrit.on-invalid-change-id=error",
+    ]);
+    insta::assert_snapshot!(output, @r#"
+    ------- stderr -------
+    Error: Invalid Change-Id footers in revisions: yostqsxwqrlt, mzvwutvlkqwt
+    Hint: Fix the footers, or set `gerrit.on-invalid-change-id = "fix"` to replace them
+    [EOF]
+    [exit status: 1]
+    "#);
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-rbb",
+            "--remote-branch=other",
+            "--config=gerrit.on-invalid-change-id=fix",
+        ])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%B",
+        "-2",
+        "refs/for/other",
+    ]);
+    insta::assert_snapshot!(output, @"
+    Change-Id: I1b76972398e6b49e8e0701307e57d55a6a6a6964
+
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+
+    [EOF]
+    ");
 }
 
 #[test]
-fn test_gerrit_upload_rejected_by_remote() {
+fn test_gerrit_upload_non_git_backend() {
     let test_env = TestEnvironment::default();
     test_env
-        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .run_jj_in(".", ["debug", "init-simple", "repo"])
         .success();
-    let remote_dir = test_env.work_dir("remote");
-    create_commit(&remote_dir, "a", &[]);
+    let work_dir = test_env.work_dir("repo");
+    create_commit(&work_dir, "a", &[]);
 
-    // create a hook on the remote that prevents pushing
-    let hook_path = test_env
-        .env_root()
-        .join("remote")
-        .join(".git")
-        .join("hooks")
-        .join("update");
+    let output = work_dir.run_jj(["gerrit", "upload", "-r", "a", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The repo is not backed by a Git repo
+    Hint: `jj gerrit` commands only work in repos using the Git backend, which is recorded in `.jj/repo/store/type`. Create a Git-backed repo with `jj git init` or `jj git clone`
+    [EOF]
+    [exit status: 1]
+    ");
+}
 
-    std::fs::write(&hook_path, "#!/bin/sh\nexit 1").unwrap();
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt as _;
+#[test]
+fn test_gerrit_upload_show_upload_set() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "-m",
+            "c\n\nChange-Id: I1111111111111111111111111111111111111111",
+        ])
+        .success();
+    create_commit(&local_dir, "d", &["c"]);
 
-        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o700)).unwrap();
-    }
+    // Ancestors of the selected revision are included
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "d", "--show-upload-set"]);
+    insta::assert_snapshot!(output, @"
+    znkkpsqq e27c6d56 d | d (new Change-Id)
+    yqosqzyt 16b0efa6 c | c (has Change-Id)
+    mzvwutvl 3bcb28c4 b | b (new Change-Id)
+    [EOF]
+    ");
 
-    test_env
-        .run_jj_in(".", ["git", "clone", "remote", "local"])
-        .success();
-    let local_dir = test_env.work_dir("local");
+    // Nothing was pushed
+    let output = remote_dir.run_jj(["util", "exec", "--", "git", "for-each-ref", "refs/for"]);
+    insta::assert_snapshot!(output, @"");
+}
+
+#[test]
+fn test_gerrit_upload_replace_change_id() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
     create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "-m",
+            "b\n\nChange-Id: I1111111111111111111111111111111111111111\nSigned-off-by: Alice",
+        ])
+        .success();
+    create_commit(&local_dir, "c", &["b"]);
 
-    // Add an explicit Change-Id footer to b
+    // The new Change-Id must be well-formed
     let output = local_dir.run_jj([
-        "describe",
-        "b",
-        "-m",
-        "b\n\nChange-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9\n",
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--replace-change-id",
+        "I1111111111111111111111111111111111111111",
+        "I2222",
     ]);
-    insta::assert_snapshot!(output, @r###"
+    insta::assert_snapshot!(output, @"
     ------- stderr -------
-    Working copy  (@) now at: mzvwutvl 887a7016 b | b
-    Parent commit (@-)      : rlvkpnrz 7d980be7 a@origin | a
+    Error: Invalid Change-Id 'I2222'
+    Hint: A Change-Id must be 'I' followed by 40 hexadecimal digits
     [EOF]
-    "###);
+    [exit status: 1]
+    ");
 
-    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
-    insta::assert_snapshot!(output, @r"
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--replace-change-id",
+        "I3333333333333333333333333333333333333333",
+        "I2222222222222222222222222222222222222222",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No revisions to upload have the Change-Id 'I3333333333333333333333333333333333333333'
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--replace-change-id",
+        "I1111111111111111111111111111111111111111",
+        "I2222222222222222222222222222222222222222",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Replaced Change-Id in 1 revisions
+    Working copy  (@) now at: vruxwmqv ffc26a16 c | c
+    Parent commit (@-)      : mzvwutvl 9f5a6ddb b | b
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "b", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    ○  b
+    │
+    ~  Change-Id: I2222222222222222222222222222222222222222
+       Signed-off-by: Alice
+    [EOF]
+    ");
+
+    // Nothing was pushed
+    let output = remote_dir.run_jj(["util", "exec", "--", "git", "for-each-ref", "refs/for"]);
+    insta::assert_snapshot!(output, @"");
+}
+
+#[test]
+fn test_gerrit_upload_edit_change_id() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+
+    // Without a terminal, the derived Change-Ids are used
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--edit-change-id",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
     ------- stderr -------
+    Change-Id for revision mzvwutvlkqwt [Id043564ef93650b06a70f92f9d91912b6a6a6964]: Id043564ef93650b06a70f92f9d91912b6a6a6964
+    Change-Id for revision yqosqzytrlsw [I19b790168e73f7a73a98deae21e807c06a6a6964]: I19b790168e73f7a73a98deae21e807c06a6a6964
     Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
-    Pushing mzvwutvl 887a7016 b | b
-    remote: error: hook declined to update refs/for/main        
-    Warning: The remote rejected the following updates:
-      refs/for/main (reason: hook declined)
-    Hint: Try checking if you have permission to push to all the bookmarks.
-    Error: Failed to push all changes to gerrit
+    Dry-run: Would push yqosqzyt 9590bf26 c | c
+    b | 1 +
+    c | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Empty input keeps the derived Change-Id, and invalid ones are rejected
+    let output = local_dir.run_jj_with(|cmd| {
+        force_interactive(cmd)
+            .args([
+                "gerrit",
+                "upload",
+                "-r",
+                "c",
+                "--remote-branch=main",
+                "--edit-change-id",
+            ])
+            .write_stdin("\nI1234\nI1111111111111111111111111111111111111111\n")
+    });
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Change-Id for revision mzvwutvlkqwt [Id043564ef93650b06a70f92f9d91912b6a6a6964]: Change-Id for revision yqosqzytrlsw [I19b790168e73f7a73a98deae21e807c06a6a6964]: Invalid Change-Id 'I1234', expected 'I' followed by 40 hexadecimal digits
+    Change-Id for revision yqosqzytrlsw [I19b790168e73f7a73a98deae21e807c06a6a6964]: Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    [EOF]
+    ");
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%B",
+        "refs/for/main",
+        "-2",
+    ]);
+    insta::assert_snapshot!(output, @"
+    c
+
+    Change-Id: I1111111111111111111111111111111111111111
+
+    b
+
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_failure_records_no_operation() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir.run_jj(["new", "-m", "empty"]).success();
+    let get_op_id = || {
+        local_dir
+            .run_jj(["op", "log", "-n1", "--no-graph", "-T", "id"])
+            .success()
+            .stdout
+            .into_raw()
+    };
+    let op_id = get_op_id();
+
+    // Rejected before anything is pushed
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "@", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload revision yqosqzytrlsw because it is empty
+    Hint: Perhaps you squashed then ran upload? Maybe you meant to upload the parent commit instead (eg. @-), or pass `--allow-empty` to upload it anyway
     [EOF]
     [exit status: 1]
     ");
+    assert_eq!(get_op_id(), op_id);
+
+    // Rejected by the remote
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--no-change-id",
+    ]);
+    assert!(!output.status.success());
+    assert_eq!(get_op_id(), op_id);
+}
+
+#[test]
+fn test_gerrit_upload_verbose_dry_run() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone_with(
+        &test_env,
+        &["a"],
+        &[(".gerrit.toml", "topic = \"my-topic\"\n")],
+    );
+    test_env.add_config("gerrit.use-repo-defaults = true");
+    create_commit(&local_dir, "b", &["a@origin"]);
+    test_env.add_config(r#"gerrit.default-remote-branch = "main""#);
+    local_dir
+        .run_jj(["config", "set", "--repo", "gerrit.default-remote", "origin"])
+        .success();
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run", "--verbose"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from `gerrit.default-remote` in repo config)
+      branch: main (from `gerrit.default-remote-branch` in user config)
+      topic: my-topic (from .gerrit.toml)
+      reviewers: none
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push mzvwutvl 69ba79bf b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote",
+        "origin",
+        "--remote-branch",
+        "other",
+        "--dry-run",
+        "--verbose",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from --remote)
+      branch: other (from --remote-branch)
+      topic: my-topic (from .gerrit.toml)
+      reviewers: none
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
+    Dry-run: Would push mzvwutvl 69ba79bf b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Only supported on dry-run
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--verbose"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the following required arguments were not provided:
+      --dry-run
+
+    Usage: jj gerrit upload --dry-run --revisions <REVISIONS> --verbose
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_strip_change_id_on_dry_run() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir
+        .run_jj(["describe", "-m", "c\n\nSigned-off-by: Alice"])
+        .success();
+    create_commit(&local_dir, "d", &["c"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "-m",
+            "d\n\nChange-Id: I1111111111111111111111111111111111111111",
+        ])
+        .success();
+    test_env.add_config("gerrit.upload.strip-change-id-on-dry-run = true");
+
+    // Descriptions which already have a Change-Id aren't shown
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "d",
+        "--remote-branch=main",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push znkkpsqq 0ebaa4cd d | d
+    b | 1 +
+    c | 1 +
+    d | 1 +
+    3 files changed, 3 insertions(+), 0 deletions(-)
+    Description of yqosqzytrlsw without Change-Id:
+    > c
+    >
+    > Signed-off-by: Alice
+    Description of yqosqzytrlsw with Change-Id:
+    > c
+    >
+    > Signed-off-by: Alice
+    > Change-Id: I19b790168e73f7a73a98deae21e807c06a6a6964
+    Description of mzvwutvlkqwt without Change-Id:
+    > b
+    Description of mzvwutvlkqwt with Change-Id:
+    > b
+    >
+    > Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_message_lint() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    let long_subject = "x".repeat(73);
+    local_dir
+        .run_jj(["describe", "-m", &format!("{long_subject}\nbody ")])
+        .success();
+    create_commit(&local_dir, "c", &["b"]);
+
+    // Not checked by default
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "c",
+            "--remote-branch=main",
+            "--dry-run",
+        ])
+        .success();
+
+    test_env.add_config("gerrit.message-lint = true");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Some descriptions don't pass `gerrit.message-lint`:
+      mzvwutvlkqwt: subject is 73 characters long, more than 72
+      mzvwutvlkqwt: subject is not followed by a blank line
+      mzvwutvlkqwt: line 2 has trailing whitespace
+    Hint: Fix the descriptions with `jj describe`, or pass `--no-lint` to upload anyway
+    [EOF]
+    [exit status: 1]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "c",
+            "--remote-branch=main",
+            "--dry-run",
+            "--no-lint",
+        ])
+        .success();
+}
+
+#[test]
+fn test_gerrit_upload_skip_snapshot() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir.run_jj(["new", "-m", "c"]).success();
+    local_dir.write_file("c", "c\n");
+    let get_last_op = || {
+        local_dir
+            .run_jj([
+                "op",
+                "log",
+                "--ignore-working-copy",
+                "-n1",
+                "--no-graph",
+                "-T",
+                "description",
+            ])
+            .success()
+    };
+
+    // The working copy isn't part of the upload, so it isn't snapshotted
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--dry-run",
+        "--skip-snapshot",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+    insta::assert_snapshot!(get_last_op(), @"new empty commit[EOF]");
+
+    // The working copy is snapshotted before uploading it
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "@",
+        "--remote-branch=main",
+        "--dry-run",
+        "--skip-snapshot",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push yqosqzyt 7bb14c2c c
+    b | 1 +
+    c | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    [EOF]
+    ");
+    insta::assert_snapshot!(get_last_op(), @"snapshot working copy[EOF]");
+}
+
+#[test]
+fn test_gerrit_upload_per_remote_settings_precedence() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone_with(
+        &test_env,
+        &["a"],
+        &[(
+            ".gerrit.toml",
+            "branch = \"repo-branch\"\ntopic = \"repo-topic\"\n",
+        )],
+    );
+    test_env.add_config("gerrit.use-repo-defaults = true");
+    create_commit(&local_dir, "b", &["a@origin"]);
+    let upload = |args: &[&str]| {
+        let mut all_args = vec!["gerrit", "upload", "-r", "b", "--dry-run", "--verbose"];
+        all_args.extend(args);
+        local_dir.run_jj(all_args)
+    };
+
+    // Only the project's defaults
+    insta::assert_snapshot!(upload(&[]), @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: repo-branch (from .gerrit.toml)
+      topic: repo-topic (from .gerrit.toml)
+      reviewers: none
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'repo-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Global config overrides .gerrit.toml
+    test_env.add_config(
+        r#"
+        gerrit.default-remote-branch = 'global-branch'
+        gerrit.default-topic = 'global-topic'
+        gerrit.reviewers = ['global-reviewer']
+        gerrit.cc = ['global-cc']
+        "#,
+    );
+    insta::assert_snapshot!(upload(&[]), @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: global-branch (from `gerrit.default-remote-branch` in user config)
+      topic: global-topic (from `gerrit.default-topic` in user config)
+      reviewer: global-reviewer (from `gerrit.reviewers` in user config)
+      cc: global-cc (from `gerrit.cc` in user config)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'global-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Per-remote config overrides global config
+    test_env.add_config(
+        r#"
+        gerrit.remotes.origin.default-remote-branch = 'origin-branch'
+        gerrit.remotes.origin.default-topic = 'origin-topic'
+        gerrit.remotes.origin.reviewers = ['origin-reviewer']
+        gerrit.remotes.origin.cc = ['origin-cc']
+        "#,
+    );
+    insta::assert_snapshot!(upload(&[]), @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: origin-branch (from `gerrit.remotes.origin.default-remote-branch` in user config)
+      topic: origin-topic (from `gerrit.remotes.origin.default-topic` in user config)
+      reviewer: origin-reviewer (from `gerrit.remotes.origin.reviewers` in user config)
+      cc: origin-cc (from `gerrit.remotes.origin.cc` in user config)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'origin-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Settings for other remotes are ignored
+    test_env.add_config(
+        r#"
+        gerrit.remotes.other.default-topic = 'other-topic'
+        gerrit.remotes.other.reviewers = ['other-reviewer']
+        "#,
+    );
+    insta::assert_snapshot!(upload(&[]), @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: origin-branch (from `gerrit.remotes.origin.default-remote-branch` in user config)
+      topic: origin-topic (from `gerrit.remotes.origin.default-topic` in user config)
+      reviewer: origin-reviewer (from `gerrit.remotes.origin.reviewers` in user config)
+      cc: origin-cc (from `gerrit.remotes.origin.cc` in user config)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'origin-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Flags override everything
+    insta::assert_snapshot!(
+        upload(&[
+            "--remote-branch=flag-branch",
+            "--topic=flag-topic",
+            "--reviewer=flag-reviewer",
+            "--cc=flag-cc",
+        ]),
+        @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: flag-branch (from --remote-branch)
+      topic: flag-topic (from --topic)
+      reviewer: flag-reviewer (from --reviewer)
+      cc: flag-cc (from --cc)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'flag-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // An empty per-remote topic disables the global one
+    test_env.add_config("gerrit.remotes.origin.default-topic = ''");
+    insta::assert_snapshot!(upload(&[]), @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: origin-branch (from `gerrit.remotes.origin.default-remote-branch` in user config)
+      topic: none
+      reviewer: origin-reviewer (from `gerrit.remotes.origin.reviewers` in user config)
+      cc: origin-cc (from `gerrit.remotes.origin.cc` in user config)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'origin-branch'
+    Dry-run: Would push mzvwutvl da4dc991 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_reviewers_file() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone_with(
+        &test_env,
+        &["a"],
+        &[(
+            ".gerrit.toml",
+            "reviewers = [\"bob@example.com\", \"carol@example.com\"]\n",
+        )],
+    );
+    test_env.add_config("gerrit.use-repo-defaults = true");
+    let gerrit = FakeGerrit::install(&remote_dir);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    std::fs::write(
+        test_env.env_root().join("reviewers"),
+        indoc::indoc! {"
+            # The review roster
+            alice@example.com
+
+              bob@example.com
+            alice@example.com
+        "},
+    )
+    .unwrap();
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--reviewers-file=../reviewers",
+        "--dry-run",
+        "--verbose",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Adding reviewers from .gerrit.toml: carol@example.com
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: main (from --remote-branch)
+      topic: none
+      reviewer: alice@example.com (from --reviewers-file)
+      reviewer: bob@example.com (from --reviewers-file)
+      reviewer: carol@example.com (from .gerrit.toml)
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push mzvwutvl 620185ab b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "--remote-branch=main",
+            "--reviewers-file=../reviewers",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"dec3a5c971a13efd34f8643ace9f0559c441f129 main r=alice%40example%2Ecom,r=bob%40example%2Ecom,r=carol%40example%2Ecom");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--reviewers-file=../missing",
+    ]);
+    assert!(!output.status.success());
+    assert!(
+        output
+            .stderr
+            .normalized()
+            .starts_with("Error: Cannot access ../missing")
+    );
+}
+
+#[test]
+fn test_gerrit_upload_remote_branch_pattern() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) =
+        set_up_remote_and_clone_with(&test_env, &["main", "release/1.0", "release/2.0"], &[]);
+    create_commit(&local_dir, "b", &["main@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=stable/*"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No branches on remote 'origin' match 'stable/*'
+    Hint: Run `jj git fetch` to update the list of remote branches
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Uploading to several branches must be confirmed
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=release/*"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: --remote-branch 'release/*' matches 2 branches: 'release/1.0', 'release/2.0'
+    Hint: Pass `--confirm` to be asked before uploading to all of them, `--yes` to upload to all of them without asking, or `--dry-run` to preview the upload
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=release/*",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branches 'release/1.0', 'release/2.0'
+    Dry-run: Would push to 'release/1.0': yostqsxw 9c8b36fd b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    Dry-run: Would push to 'release/2.0': yostqsxw 9c8b36fd b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Without a terminal, --confirm can't be answered, but --yes can be used
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=release/*",
+        "--confirm",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload 1 revisions without confirmation
+    Hint: Pass `--yes` instead of `--confirm` to upload without confirmation
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "--remote-branch=release/*",
+            "--yes",
+        ])
+        .success();
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branches 'release/1.0', 'release/2.0'
+    Pushing to 'release/1.0': yostqsxw 9c8b36fd b | b
+    Pushing to 'release/2.0': yostqsxw 9c8b36fd b | b
+    [EOF]
+    ");
+
+    let output = local_dir
+        .run_jj_with(|cmd| {
+            force_interactive(cmd)
+                .args([
+                    "gerrit",
+                    "upload",
+                    "-r",
+                    "b",
+                    "--remote-branch=release/*",
+                    "--confirm",
+                ])
+                .write_stdin("y\n")
+        })
+        .success();
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    About to upload 1 revisions (1 heads) to Gerrit branches 'release/1.0', 'release/2.0'. Continue? (yn): Found 1 heads to push to Gerrit (remote 'origin'), target branches 'release/1.0', 'release/2.0'
+    Pushing to 'release/1.0': yostqsxw 9c8b36fd b | b
+    Pushing to 'release/2.0': yostqsxw 9c8b36fd b | b
+    [EOF]
+    ");
+
+    // A pattern matching a single branch doesn't need confirmation
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=m*"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yostqsxw 9c8b36fd b | b
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname) %(subject)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main b
+    refs/for/release/1.0 b
+    refs/for/release/2.0 b
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_topic() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--topic="]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The topic passed to --topic must not be empty
+    [EOF]
+    [exit status: 1]
+    ");
+
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--topic=my-topic"])
+        .success();
+    test_env.add_config("gerrit.default-topic = 'fix: a & b'");
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--topic=other"])
+        .success();
+    // Characters which aren't allowed in ref names are encoded as well
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--topic=a~b..c.lock",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=my%2Dtopic
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=fix%3A%20a%20%26%20b
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=other
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=a%7Eb%2E%2Ec%2Elock
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--dry-run",
+        "--verbose",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: a (from --remote-branch)
+      topic: fix: a & b (from `gerrit.default-topic` in user config)
+      reviewers: none
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'a'
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_topic_prefix() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--topic=t",
+        "--topic-prefix=my team/",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: invalid value 'my team/' for '--topic-prefix <PREFIX>': The topic prefix must not contain whitespace, got 'my team/'
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--topic-prefix=myteam/",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: There is no topic to apply --topic-prefix to
+    Hint: Pass `--topic`, or configure `gerrit.default-topic`
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Both the topic passed on the command line and the configured one are
+    // prefixed
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--topic=t",
+            "--topic-prefix=myteam/",
+        ])
+        .success();
+    test_env.add_config("gerrit.default-topic = 'feature'");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--topic-prefix=myteam/",
+        "--dry-run",
+        "--verbose",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Resolved upload settings:
+      remote: origin (from Git's default push remote)
+      branch: a (from --remote-branch)
+      topic: myteam/feature (from `gerrit.default-topic` in user config with --topic-prefix)
+      reviewers: none
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'a'
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=myteam%2Ft
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_reviewer() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--reviewer="]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The reviewer passed to --reviewer must not be empty
+    [EOF]
+    [exit status: 1]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--reviewer=alice@example.com",
+            "--reviewer=my team",
+        ])
+        .success();
+
+    // Configured reviewers are only added if `--reviewer` isn't passed
+    test_env.add_config(r#"gerrit.reviewers = ["bob@example.com", "carol@example.com"]"#);
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--reviewer=alice@example.com",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a r=alice%40example%2Ecom,r=my%20team
+    81b723522d1c1a583a045eab5bfb323e45e6198d a r=bob%40example%2Ecom,r=carol%40example%2Ecom
+    81b723522d1c1a583a045eab5bfb323e45e6198d a r=alice%40example%2Ecom
+    ");
+
+    test_env.add_config(r#"gerrit.reviewers = ["", "bob@example.com"]"#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: `gerrit.reviewers` must not contain empty reviewers
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_cc() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--cc="]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The account passed to --cc must not be empty
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Accounts can be both reviewers and CCed
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--topic=t",
+            "--reviewer=alice@example.com",
+            "--cc=alice@example.com",
+            "--cc=bob@example.com",
+        ])
+        .success();
+    test_env.add_config(r#"gerrit.cc = ["carol@example.com"]"#);
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a topic=t,r=alice%40example%2Ecom,cc=alice%40example%2Ecom,cc=bob%40example%2Ecom
+    81b723522d1c1a583a045eab5bfb323e45e6198d a cc=carol%40example%2Ecom
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_require_reviewer() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    test_env.add_config("gerrit.require-reviewer = true");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload changes without reviewers
+    Hint: Pass `--reviewer` or `--cc`, or configure `gerrit.reviewers` or `gerrit.cc`. Reviewers are required because `gerrit.require-reviewer` is enabled
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Reviewers and CCed accounts from the arguments or the config count
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--reviewer=alice@example.com",
+        ])
+        .success();
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--cc=bob@example.com",
+        ])
+        .success();
+    test_env.add_config(r#"gerrit.reviewers = ["carol@example.com"]"#);
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a r=alice%40example%2Ecom
+    81b723522d1c1a583a045eab5bfb323e45e6198d a cc=bob%40example%2Ecom
+    81b723522d1c1a583a045eab5bfb323e45e6198d a r=carol%40example%2Ecom
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_wip() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--wip", "--ready"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--wip' cannot be used with '--ready'
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --wip
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--wip"])
+        .success();
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--ready"])
+        .success();
+    test_env.add_config("gerrit.upload-wip = true");
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--ready"])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a wip
+    81b723522d1c1a583a045eab5bfb323e45e6198d a ready
+    81b723522d1c1a583a045eab5bfb323e45e6198d a wip
+    81b723522d1c1a583a045eab5bfb323e45e6198d a ready
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_private() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--private",
+        "--remove-private",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--private' cannot be used with '--remove-private'
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --private
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--private",
+            "--wip",
+        ])
+        .success();
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--remove-private"])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a wip,private
+    81b723522d1c1a583a045eab5bfb323e45e6198d a remove-private
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_hashtag() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--hashtag="]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The hashtag passed to --hashtag must not be empty
+    [EOF]
+    [exit status: 1]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--hashtag=perf",
+            "--hashtag=50%,fast",
+        ])
+        .success();
+    test_env.add_config(r#"gerrit.default-hashtags = ["cleanup"]"#);
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a hashtag=perf,hashtag=50%25%2Cfast
+    81b723522d1c1a583a045eab5bfb323e45e6198d a hashtag=cleanup
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_label() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--label=Code-Review",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: invalid value 'Code-Review' for '--label <LABEL>': Expected LABEL+N or LABEL-N, got 'Code-Review'
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--label=Verified+10",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: invalid value 'Verified+10' for '--label <LABEL>': Expected LABEL+N or LABEL-N, got 'Verified+10'
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "-b",
+            "a",
+            "--label=Code-Review+2",
+            "--label=Verified-1",
+            "--topic=t",
+            "--reviewer=alice@example.com",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"81b723522d1c1a583a045eab5bfb323e45e6198d a topic=t,r=alice%40example%2Ecom,l=Code-Review+2,l=Verified-1");
+}
+
+#[test]
+fn test_gerrit_upload_message() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+
+    // The message is encoded so that Gerrit decodes it unchanged
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b|c",
+            "-b",
+            "a",
+            "--message=Fix foo_bar, 100% done",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    9fce2598bc678796fe0a1c0aad4a657de356f3b0 a m=Fix%20foo%5Fbar%2C%20100%25%20done
+    81b723522d1c1a583a045eab5bfb323e45e6198d a m=Fix%20foo%5Fbar%2C%20100%25%20done
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_draft() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit", "upload", "-r", "b", "-b", "a", "--draft", "--ready",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--draft' cannot be used with '--ready'
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --draft
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a", "--draft"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'a' as drafts
+    Pushing mzvwutvl 3bcb28c4 b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "-b", "a"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'a'
+    Pushing yqosqzyt cfd7d90d c | c
+    View change at:
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d a draft
+    9fce2598bc678796fe0a1c0aad4a657de356f3b0 a
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_change_id_prefix() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    test_env.add_config("gerrit.change-id-prefix = '6a6a69'");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "-b", "a"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Invalid `gerrit.change-id-prefix` '6a6a69'
+    Hint: It must be 8 lowercase hexadecimal characters, which pad the jj change id to a 40-character Change-Id
+    [EOF]
+    [exit status: 1]
+    ");
+
+    test_env.add_config("gerrit.change-id-prefix = '00c0ffee'");
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "-b", "a"])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/a",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Change-Id: Id043564ef93650b06a70f92f9d91912b00c0ffee
+
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_update_change_id() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir
+        .run_jj(["describe", "-r", "b", "-m", "b\n\nChange-Id: Ibad\n"])
+        .success();
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "-r",
+            "c",
+            "-m",
+            "c\n\nChange-Id: I1111111111111111111111111111111111111111\nChange-Id: \
+             I2222222222222222222222222222222222222222\n",
+        ])
+        .success();
+
+    // Multiple Change-Ids are still rejected
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "-b",
+        "a",
+        "--update-change-id",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Updating Change-Id footer in revision mzvwutvlkqwt
+    Error: Multiple Change-Id footers in revision vruxwmqvtpmx
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-b",
+        "a",
+        "--update-change-id",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Updating Change-Id footer in revision mzvwutvlkqwt
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'a'
+    Pushing mzvwutvl 40973031 b | b
+    [EOF]
+    ");
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/a",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+
+    [EOF]
+    ");
+
+    // The local description is left alone
+    let output = local_dir.run_jj(["log", "-r", "b", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    ○  b
+    │
+    ~  Change-Id: Ibad
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_batches_distinct_refs() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) =
+        set_up_remote_and_clone_with(&test_env, &["main", "release"], &[]);
+    let gerrit = FakeGerrit::install(&remote_dir);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["main@origin"]);
+    create_commit(&local_dir, "c", &["release@origin"]);
+    create_commit(&local_dir, "d", &["main@origin"]);
+
+    // The heads for 'main' can't be pushed at once, but the one for 'release'
+    // is pushed along with the first of them
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c|d", "--auto-branch"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 3 heads to push to Gerrit (remote 'origin'), target branches 'main', 'release'
+    Pushing to 'main': kpqxywon 66d0ea04 d | d
+    Pushing to 'release': yostqsxw 16f1e6b4 c | c
+    Pushing to 'main': yqosqzyt dae20482 b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+      https://gerrit.example.com/c/repo/+/3
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.receives(), @"
+    main release
+    main
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_overlapping_revisions() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    create_commit(&local_dir, "d", &["a@origin"]);
+
+    // b is pushed along with c, so only two heads are pushed
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "-r",
+        "c",
+        "-r",
+        "d",
+        "--remote-branch=main",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Not pushing b separately because it is an ancestor of yqosqzyt 9590bf26 c | c
+    Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push yostqsxw 674e3ea3 d | d
+    d | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    Dry-run: Would push yqosqzyt 9590bf26 c | c
+    b | 1 +
+    c | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_order() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+
+    // Newer heads are pushed first by default
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt cfd7d90d c | c
+    Pushing mzvwutvl 3bcb28c4 b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+
+    test_env.add_config(r#"gerrit.upload.order = "topo""#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    Pushing yqosqzyt cfd7d90d c | c
+    View change at:
+      https://gerrit.example.com/c/repo/+/2
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    9fce2598bc678796fe0a1c0aad4a657de356f3b0 main
+    81b723522d1c1a583a045eab5bfb323e45e6198d main
+    81b723522d1c1a583a045eab5bfb323e45e6198d main
+    9fce2598bc678796fe0a1c0aad4a657de356f3b0 main
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_repo_defaults() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone_with(
+        &test_env,
+        &["a"],
+        &[(
+            ".gerrit.toml",
+            indoc::indoc! {r#"
+                    branch = "main"
+                    topic = "my topic"
+                    reviewers = ["alice@example.com", "bob@example.com"]
+                "#},
+        )],
+    );
+    let gerrit = FakeGerrit::install(&remote_dir);
+    let mut settings = sideband_insta_settings();
+    settings.add_filter(r"(?m)^remote:.*\n", "");
+    let _guard = settings.bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // The file is ignored unless the user opts in
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No target branch specified via --remote-branch, and no 'gerrit.default-remote-branch' was found
+    [EOF]
+    [exit status: 1]
+    ");
+    local_dir
+        .run_jj([
+            "config",
+            "set",
+            "--repo",
+            "gerrit.use-repo-defaults",
+            "true",
+        ])
+        .success();
+
+    // The branch, topic and reviewers are taken from the checked-in file
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Adding reviewers from .gerrit.toml: alice@example.com, bob@example.com
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl b6f1a0ee b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+
+    // Config and command-line arguments take precedence
+    test_env.add_config(r#"gerrit.default-remote-branch = "other""#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Adding reviewers from .gerrit.toml: alice@example.com, bob@example.com
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
+    Dry-run: Would push mzvwutvl b6f1a0ee b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Adding reviewers from .gerrit.toml: alice@example.com, bob@example.com
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl b6f1a0ee b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    06d5d1989eb68495bd8df508fca7fbe692ccdb05 main topic=my%20topic,r=alice%40example%2Ecom,r=bob%40example%2Ecom
+    06d5d1989eb68495bd8df508fca7fbe692ccdb05 main topic=my%20topic,r=alice%40example%2Ecom,r=bob%40example%2Ecom
+    ");
+
+    // Unknown keys are ignored with a warning
+    local_dir.write_file(".gerrit.toml", "reviewer = \"carol@example.com\"\n");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: Ignoring unknown key 'reviewer' in .gerrit.toml
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
+    Dry-run: Would push mzvwutvl 6e893411 b | b
+    .gerrit.toml | 4 +---
+    b            | 1 +
+    2 files changed, 2 insertions(+), 3 deletions(-)
+    [EOF]
+    ");
+
+    // Branches which aren't plain branch names are refused
+    local_dir.write_file(".gerrit.toml", "branch = \"main%submit\"\n");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Target branch 'main%submit' contains '%'
+    Hint: Gerrit would read the part after '%' as push options. Use the upload flags, e.g. `--topic` or `--reviewer`, to set push options
+    Hint: The branch is set by `branch` in .gerrit.toml
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Malformed files are reported
+    local_dir.write_file(".gerrit.toml", "reviewers = \"carol@example.com\"\n");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @r#"
+    ------- stderr -------
+    Error: Failed to parse .gerrit.toml
+    Caused by: TOML parse error at line 1, column 13
+      |
+    1 | reviewers = "carol@example.com"
+      |             ^^^^^^^^^^^^^^^^^^^
+    invalid type: string "carol@example.com", expected a sequence
+    [EOF]
+    [exit status: 1]
+    "#);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_gerrit_upload_open() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    test_env.add_config(r#"gerrit.browser = ["sh", "-c", "echo $0 >> ../opened"]"#);
+    let opened_path = test_env.env_root().join("opened");
+
+    // Nothing is opened on dry-run
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "c",
+            "--remote-branch=main",
+            "--open",
+            "--dry-run",
+        ])
+        .success();
+    assert!(!opened_path.exists());
+
+    // Each reported change is opened
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--open",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:   https://gerrit.example.com/c/repo/+/2 c [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+    insta::assert_snapshot!(std::fs::read_to_string(&opened_path).unwrap(), @"
+    https://gerrit.example.com/c/repo/+/1
+    https://gerrit.example.com/c/repo/+/2
+    ");
+
+    // A failing browser is only reported
+    test_env.add_config(r#"gerrit.browser = ["sh", "-c", "exit 3"]"#);
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--open",
+        "--quiet-remote",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    Warning: Browser 'sh' exited with exit status: 3 while opening https://gerrit.example.com/c/repo/+/1
+    Warning: Browser 'sh' exited with exit status: 3 while opening https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_gerrit_upload_after_push_hook() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    test_env.add_config(r#"gerrit.upload.after-push = ["sh", "-c", "cat > ../after-push.json"]"#);
+
+    // The hook isn't run on dry-run
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "c",
+            "--remote-branch=main",
+            "--dry-run",
+        ])
+        .success();
+    let hook_output_path = test_env.env_root().join("after-push.json");
+    assert!(!hook_output_path.exists());
+
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"])
+        .success();
+    insta::assert_snapshot!(std::fs::read_to_string(&hook_output_path).unwrap(), @r#"{"remote":"origin","heads":[{"commit":"ab6776c073b82fbbd2cd0858482a9646afd56f85","ref":"refs/for/main"}],"changes":[{"change_id":"I19b790168e73f7a73a98deae21e807c06a6a6964","commit":"ab6776c073b82fbbd2cd0858482a9646afd56f85","number":2},{"change_id":"Id043564ef93650b06a70f92f9d91912b6a6a6964","commit":"81b723522d1c1a583a045eab5bfb323e45e6198d","number":1}]}"#);
+
+    // Failing hooks don't fail the upload
+    test_env.add_config(r#"gerrit.upload.after-push = ["sh", "-c", "exit 3"]"#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b
+    remote:   https://gerrit.example.com/c/repo/+/2 c
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    Warning: After-push hook 'sh' exited with exit status: 3
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_change_id_from_commit_tree() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir.run_jj(["duplicate", "b"]).success();
+    test_env.add_config("gerrit.change-id-from = 'commit-tree'");
+
+    let change_ids = |dump_dir: &str| {
+        local_dir
+            .run_jj([
+                "gerrit",
+                "upload",
+                "-r",
+                "mutable()",
+                "--remote-branch=main",
+                &format!("--dump-descriptions=../{dump_dir}"),
+            ])
+            .success();
+        let dump_dir = test_env.work_dir(dump_dir);
+        let mut change_ids = String::new();
+        for entry in std::fs::read_dir(dump_dir.root())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .sorted()
+        {
+            let description = dump_dir.read_file(&entry).to_string();
+            let change_id = description.lines().last().unwrap();
+            writeln!(change_ids, "{}: {change_id}", entry.to_str().unwrap()).unwrap();
+        }
+        change_ids
+    };
+
+    // Revisions with the same tree and description get the same Change-Id
+    insta::assert_snapshot!(change_ids("dump1"), @"
+    mzvwutvlkqwt: Change-Id: I7b40484d067c32459f28fbf628d049a943fed065
+    yqosqzytrlsw: Change-Id: I7b40484d067c32459f28fbf628d049a943fed065
+    ");
+
+    // Changing the description results in a new Change-Id
+    local_dir
+        .run_jj(["describe", "b", "-m", "b updated"])
+        .success();
+    insta::assert_snapshot!(change_ids("dump2"), @"
+    mzvwutvlkqwt: Change-Id: I1dc3d7b0875856770ac1bf7f393c3d288de424b7
+    yqosqzytrlsw: Change-Id: I7b40484d067c32459f28fbf628d049a943fed065
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_duplicate_revisions() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // The same revision selected repeatedly is uploaded once
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "@",
+        "-r",
+        "@",
+        "-r",
+        "b",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"81b723522d1c1a583a045eab5bfb323e45e6198d main");
+}
+
+#[test]
+fn test_gerrit_upload_dump_descriptions() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--trailer=Reviewed-by=Alice <alice@example.com>",
+        "--dump-descriptions=../descriptions",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push yqosqzyt 9590bf26 c | c
+    Wrote 2 descriptions to ../descriptions
+    [EOF]
+    ");
+    let dump_dir = test_env.work_dir("descriptions");
+    let mut dump = String::new();
+    for entry in std::fs::read_dir(dump_dir.root())
+        .unwrap()
+        .sorted_by_key(|entry| entry.as_ref().unwrap().file_name())
+    {
+        let name = entry.unwrap().file_name();
+        writeln!(dump, "== {}", name.to_str().unwrap()).unwrap();
+        dump.push_str(&dump_dir.read_file(name).to_string());
+    }
+    insta::assert_snapshot!(dump, @"
+    == mzvwutvlkqwt
+    b
+
+    Reviewed-by: Alice <alice@example.com>
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+    == yqosqzytrlsw
+    c
+
+    Reviewed-by: Alice <alice@example.com>
+    Change-Id: I19b790168e73f7a73a98deae21e807c06a6a6964
+    ");
+
+    // Nothing was pushed
+    let output = remote_dir.run_jj(["util", "exec", "--", "git", "for-each-ref", "refs/for"]);
+    insta::assert_snapshot!(output, @"");
+}
+
+#[test]
+fn test_gerrit_upload_rejected_by_remote() {
+    let test_env = TestEnvironment::default();
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "remote"])
+        .success();
+    let remote_dir = test_env.work_dir("remote");
+    create_commit(&remote_dir, "a", &[]);
+
+    // create a hook on the remote that prevents pushing
+    let hook_path = test_env
+        .env_root()
+        .join("remote")
+        .join(".git")
+        .join("hooks")
+        .join("update");
+
+    std::fs::write(&hook_path, "#!/bin/sh\nexit 1").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o700)).unwrap();
+    }
+
+    test_env
+        .run_jj_in(".", ["git", "clone", "remote", "local"])
+        .success();
+    let local_dir = test_env.work_dir("local");
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // Add an explicit Change-Id footer to b
+    let output = local_dir.run_jj([
+        "describe",
+        "b",
+        "-m",
+        "b\n\nChange-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9\n",
+    ]);
+    insta::assert_snapshot!(output, @r###"
+    ------- stderr -------
+    Working copy  (@) now at: mzvwutvl 887a7016 b | b
+    Parent commit (@-)      : rlvkpnrz 7d980be7 a@origin | a
+    [EOF]
+    "###);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @r"
+    ------- stderr -------
+    All revisions to upload already have Change-Id trailers, so none are added
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 887a7016 b | b
+    remote: error: hook declined to update refs/for/main        
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Error: Failed to push all changes to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_uploaded_revset() {
+    let test_env = TestEnvironment::default();
+    test_env.run_jj_in(".", ["git", "init", "repo"]).success();
+    let work_dir = test_env.work_dir("repo");
+    create_commit(&work_dir, "missing", &[]);
+    create_commit(&work_dir, "valid", &["missing"]);
+    create_commit(&work_dir, "invalid", &["valid"]);
+    work_dir
+        .run_jj([
+            "describe",
+            "valid",
+            "-m",
+            "valid\n\nChange-Id: I1111111111111111111111111111111111111111\n",
+        ])
+        .success();
+    work_dir
+        .run_jj([
+            "describe",
+            "invalid",
+            "-m",
+            "invalid\n\nChange-Id: malformed\n",
+        ])
+        .success();
+
+    let output = work_dir.run_jj(["log", "-r", "gerrit_uploaded()", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    ○  valid
+    │
+    ~  Change-Id: I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+
+    let output = work_dir.run_jj(["log", "-r", "gerrit_uploaded(x)"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Failed to parse revset: Function `gerrit_uploaded`: Expected 0 arguments
+    Caused by:  --> 1:17
+      |
+    1 | gerrit_uploaded(x)
+      |                 ^
+      |
+      = Function `gerrit_uploaded`: Expected 0 arguments
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // An invalid trailer key only fails revsets that use gerrit_uploaded()
+    test_env.add_config("gerrit.change-id-trailer-key = 1");
+    let output = work_dir.run_jj(["log", "-r", "valid", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    ○  valid
+    │
+    ~  Change-Id: I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+    let output = work_dir.run_jj(["log", "-r", "gerrit_uploaded()"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Failed to parse revset: Invalid type or value for gerrit.change-id-trailer-key
+    Caused by:  --> 1:1
+      |
+    1 | gerrit_uploaded()
+      | ^-------------^
+      |
+      = Invalid type or value for gerrit.change-id-trailer-key
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_conflicted() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit_with_files(&local_dir, "b", &["a@origin"], &[("file", "b\n")]);
+    create_commit_with_files(&local_dir, "c", &["a@origin"], &[("file", "c\n")]);
+    create_commit(&local_dir, "d", &["b", "c"]);
+
+    // Commits which are already conflicted aren't mistaken for conflicting
+    // rebases
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "d", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yostqsxw fa8415ed d | (conflict) d
+    [EOF]
+    ");
+
+    // Rebasing them onto a revision which doesn't resolve the conflict is fine
+    // as well
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "d",
+        "--rebase-on=a@origin",
+        "--remote-branch=main",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push yostqsxw fa8415ed d | (conflict) d
+    d    | 1 +
+    file | 7 +++++++
+    2 files changed, 8 insertions(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_rebase_on() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit_with_files(&local_dir, "b", &["a@origin"], &[("file", "b\n")]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+    create_commit(&local_dir, "d", &["c"]);
+    create_commit_with_files(&local_dir, "e", &["a@origin"], &[("file", "e\n")]);
+
+    // Cannot rebase onto a descendant
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--rebase-on=d",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Cannot rebase revision yqosqzytrlsw onto its own descendant yostqsxwqrlt
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Conflicting rebase aborts the upload
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "e",
+        "--rebase-on=b",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Rebasing revision kpqxywonksrl would create conflicts
+    Hint: Rebase the revisions locally with `jj rebase` and resolve the conflicts, then upload again
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "d",
+        "--rebase-on=b",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yostqsxw 2f19519a d | d
+    [EOF]
+    ");
+
+    // The local commits are left untouched
+    let output = local_dir.run_jj(["log", "-r", "all()"]);
+    insta::assert_snapshot!(output, @"
+    @  kpqxywon test.user@example.com 2001-02-03 08:05:18 e 83a4777d
+    │  e
+    │ ○  yostqsxw test.user@example.com 2001-02-03 08:05:16 d 2f19519a
+    │ │  d
+    │ ○  yqosqzyt test.user@example.com 2001-02-03 08:05:14 c cfd7d90d
+    ├─╯  c
+    │ ○  mzvwutvl test.user@example.com 2001-02-03 08:05:12 b 0d37f3e0
+    ├─╯  b
+    ◆  rlvkpnrz test.user@example.com 2001-02-03 08:05:09 a@origin 7d980be7
+    │  a
+    ◆  zzzzzzzz root() 00000000
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%s",
+        "--stat",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    d
+
+     d | 1 +
+     1 file changed, 1 insertion(+)
+    c
+
+     c | 1 +
+     1 file changed, 1 insertion(+)
+    b
+
+     file | 1 +
+     1 file changed, 1 insertion(+)
+    a
+
+     a | 1 +
+     1 file changed, 1 insertion(+)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_confirm_threshold() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    test_env.add_config("gerrit.upload.confirm-threshold = 1");
+
+    // Small uploads don't need confirmation
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+
+    // Large uploads can't be confirmed in a non-interactive session
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload 2 revisions without confirmation
+    Hint: Pass `--yes` to upload without confirmation
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj_with(|cmd| {
+        force_interactive(cmd)
+            .args(["gerrit", "upload", "-r", "c", "--remote-branch=main"])
+            .write_stdin("n\n")
+    });
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    About to upload 2 revisions (1 heads) to Gerrit. Continue? (yn): Upload aborted.
+    [EOF]
+    ");
+
+    let output = local_dir.run_jj_with(|cmd| {
+        force_interactive(cmd)
+            .args(["gerrit", "upload", "-r", "c", "--remote-branch=other"])
+            .write_stdin("y\n")
+    });
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    About to upload 2 revisions (1 heads) to Gerrit. Continue? (yn): Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
+    Pushing yqosqzyt 9590bf26 c | c
+    [EOF]
+    ");
+
+    // --yes always skips confirmation
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=third",
+        "--yes",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'third'
+    Pushing yqosqzyt 9590bf26 c | c
+    [EOF]
+    ");
+
+    // --confirm always asks for confirmation
+    let output = local_dir.run_jj_with(|cmd| {
+        force_interactive(cmd)
+            .args([
+                "gerrit",
+                "upload",
+                "-r",
+                "b",
+                "--remote-branch=main",
+                "--confirm",
+            ])
+            .write_stdin("n\n")
+    });
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    About to upload 1 revisions (1 heads) to Gerrit. Continue? (yn): Upload aborted.
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_confirm_heads_threshold() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+    create_commit(&local_dir, "d", &["a@origin"]);
+    test_env.add_config("gerrit.upload.confirm-threshold = 10");
+    test_env.add_config("gerrit.upload.confirm-heads-threshold = 2");
+
+    // Few revisions, but more heads than the threshold
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c|d", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload 3 revisions without confirmation
+    Hint: Pass `--yes` to upload without confirmation
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj_with(|cmd| {
+        force_interactive(cmd)
+            .args(["gerrit", "upload", "-r", "b|c|d", "--remote-branch=main"])
+            .write_stdin("n\n")
+    });
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    About to upload 3 revisions (3 heads) to Gerrit. Continue? (yn): Upload aborted.
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"");
+
+    // Heads within the threshold don't need confirmation
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt cfd7d90d c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 c [NEW]
+    remote:
+    Pushing mzvwutvl 3bcb28c4 b | b
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/2 b [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_submit() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to submit changes on upload
+    Hint: Set `gerrit.allow-auto-submit = true` to allow `--submit`
+    [EOF]
+    [exit status: 1]
+    ");
+
+    test_env.add_config("gerrit.allow-auto-submit = true");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: The uploaded changes will be merged into 'main' immediately if they meet all submit requirements
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main%submit
+    [EOF]
+    ");
+
+    // Work-in-progress and draft changes can't be submitted
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+        "--wip",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--submit' cannot be used with '--wip'
+
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --submit
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+        "--draft",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--submit' cannot be used with '--draft'
+
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --submit
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+    test_env.add_config("gerrit.upload-wip = true");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to submit changes uploaded as work in progress
+    Hint: `gerrit.upload-wip` is enabled, pass `--ready` to upload the changes as ready for review
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+        "--ready",
+        "--dry-run",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: The uploaded changes will be merged into 'main' immediately if they meet all submit requirements
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_multiple_target_branches() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) =
+        set_up_remote_and_clone_with(&test_env, &["main", "release"], &[]);
+    create_commit(&local_dir, "b", &["main@origin"]);
+    create_commit(&local_dir, "c", &["release@origin"]);
+    create_commit(&local_dir, "d", &["root()"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: The revisions to upload are based on different branches: 'release', 'main'
+    Hint: Upload each stack separately, or use `--auto-branch` to push each stack to the branch it is based on
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c|d", "--auto-branch"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No target branch specified via --remote-branch, and no 'gerrit.default-remote-branch' was found
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b|c|d",
+        "--auto-branch",
+        "--remote-branch=other",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 3 heads to push to Gerrit (remote 'origin'), target branches 'other', 'release', 'main'
+    Pushing to 'other': kpqxywon 49961c47 d | d
+    Pushing to 'release': yostqsxw 16f1e6b4 c | c
+    Pushing to 'main': yqosqzyt dae20482 b | b
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname) %(subject)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main b
+    refs/for/other d
+    refs/for/release c
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_commit_hook_compatibility() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "b",
+            "-m",
+            concat!(
+                "b\n\n",
+                "Change-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9\n",
+                "Signed-off-by: Lucky K Maintainer <lucky@maintainer.example.org>\n",
+            ),
+        ])
+        .success();
+
+    // By default, trailers are left in place
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Change-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9
+    Signed-off-by: Lucky K Maintainer <lucky@maintainer.example.org>
+
+    [EOF]
+    ");
+
+    test_env.add_config("gerrit.commit-hook-compatibility = true");
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=other"])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/other",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Signed-off-by: Lucky K Maintainer <lucky@maintainer.example.org>
+    Change-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9
+
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_no_change_id() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--no-change-id",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+
+    // The local commit is pushed as-is
+    let local_commit_id = local_dir
+        .run_jj(["log", "-r", "b", "--no-graph", "-T", "commit_id"])
+        .success()
+        .stdout
+        .into_raw();
+    let remote_commit_id = remote_dir
+        .run_jj(["util", "exec", "--", "git", "rev-parse", "refs/for/main"])
+        .success()
+        .stdout
+        .into_raw();
+    assert_eq!(local_commit_id, remote_commit_id.trim_end());
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_no_change_id_with_trailer() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // Trailers are still added, but no Change-Id
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--no-change-id",
+        "--trailer=Bug=123",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "-1",
+        "--format=%B",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Bug: 123
+
+    [EOF]
+    ");
+    // The trailers are only added to the pushed commit
+    let output = local_dir.run_jj(["log", "-r", "b", "--no-graph", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    b
+    [EOF]
+    ");
+
+    // Change numbers are matched by Change-Id, so they can't be recorded
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--no-change-id",
+        "--record-change-number",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--no-change-id' cannot be used with '--record-change-number'
+
+    Usage: jj gerrit upload --revisions <REVISIONS> --remote-branch <REMOTE_BRANCH> --no-change-id
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_gerrit_upload_ignored_push_option() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    let hook_path = remote_dir
+        .root()
+        .join(".git")
+        .join("hooks")
+        .join("pre-receive");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh\necho 'warning: option submit ignored: change is not ready for submission' \
+         >&2\necho 'warning: submit requirements are not met' >&2\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o700)).unwrap();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    test_env.add_config("gerrit.allow-auto-submit = true");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--submit",
+    ]);
+    sideband_insta_settings().bind(|| {
+        insta::assert_snapshot!(output, @"
+        ------- stderr -------
+        Warning: The uploaded changes will be merged into 'main' immediately if they meet all submit requirements
+        Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+        Pushing mzvwutvl 3bcb28c4 b | b
+        remote: warning: option submit ignored: change is not ready for submission
+        remote: warning: submit requirements are not met
+        Warning: Gerrit ignored option 'submit': option submit ignored: change is not ready for submission
+        [EOF]
+        ");
+    });
+}
+
+#[test]
+fn test_gerrit_upload_fake_gerrit() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:   https://gerrit.example.com/c/repo/+/2 c [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+
+    // Uploading again to the same branch updates the existing changes
+    local_dir
+        .run_jj(["describe", "b", "-m", "b updated"])
+        .success();
+    test_env.add_config("gerrit.allow-auto-submit = true");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--submit",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: The uploaded changes will be merged into 'main' immediately if they meet all submit requirements
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 3313eb1f c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b updated
+    remote:   https://gerrit.example.com/c/repo/+/2 c
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    ab6776c073b82fbbd2cd0858482a9646afd56f85 main
+    0729ab59774b47f948efa559720e9c5e9e234000 main submit
+    ");
+
+    // Pushes without Change-Id are rejected like Gerrit does
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--no-change-id",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 3313eb1f c | c
+    remote: ERROR: commit 2c47b3d: missing Change-Id in message footer
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: pre-receive hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Error: Failed to push all changes to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_quiet_remote() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // Only the change URLs are shown
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--quiet-remote",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+
+    // All messages are shown if the push fails
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=main",
+        "--no-change-id",
+        "--quiet-remote",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    remote: ERROR: commit 3bcb28c: missing Change-Id in message footer
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: pre-receive hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Error: Failed to push all changes to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_keep_going() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+    create_commit(&local_dir, "d", &["a@origin"]);
+    // Only c has a Change-Id, so the other heads are rejected with
+    // --no-change-id
+    local_dir
+        .run_jj([
+            "describe",
+            "c",
+            "-m",
+            "c\n\nChange-Id: I1111111111111111111111111111111111111111",
+        ])
+        .success();
+
+    // By default, the upload stops at the first failure
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b|c|d",
+        "--remote-branch=main",
+        "--no-change-id",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 3 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt dcbb8799 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 c [NEW]
+    remote:
+    Pushing yostqsxw 674e3ea3 d | d
+    remote: ERROR: commit 674e3ea: missing Change-Id in message footer
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: pre-receive hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Error: Failed to push all changes to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    dcbb87994f80b505dc22ed992aca8adbb7e6225d main
+    674e3ea354643e606c689b7350d39e22a8bb8461 main
+    ");
+
+    // With --keep-going, the remaining heads are still pushed
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b|c|d",
+        "--remote-branch=main",
+        "--no-change-id",
+        "--keep-going",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 3 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt dcbb8799 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 c
+    remote:
+    Pushing yostqsxw 674e3ea3 d | d
+    remote: ERROR: commit 674e3ea: missing Change-Id in message footer
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: pre-receive hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Pushing mzvwutvl 3bcb28c4 b | b
+    remote: ERROR: commit 3bcb28c: missing Change-Id in message footer
+    Warning: The remote rejected the following updates:
+      refs/for/main (reason: pre-receive hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    Uploaded heads:
+      yqosqzyt dcbb8799 c | c
+    Failed heads:
+      yostqsxw 674e3ea3 d | d
+      mzvwutvl 3bcb28c4 b | b
+    Error: Failed to push 2 of 3 heads to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    dcbb87994f80b505dc22ed992aca8adbb7e6225d main
+    674e3ea354643e606c689b7350d39e22a8bb8461 main
+    dcbb87994f80b505dc22ed992aca8adbb7e6225d main
+    674e3ea354643e606c689b7350d39e22a8bb8461 main
+    3bcb28c476e3b0d8fa6d5f3f5f5969c502be5027 main
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_keep_going_partly_rejected_batch() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) =
+        set_up_remote_and_clone_with(&test_env, &["main", "release"], &[]);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    // Only reject the push for 'release', so that the head for 'main' in the
+    // same batch is still uploaded
+    let hook_path = remote_dir.root().join(".git").join("hooks").join("update");
+    std::fs::write(&hook_path, "#!/bin/sh\ntest \"$1\" != refs/for/release\n").unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o700)).unwrap();
+    }
+    create_commit(&local_dir, "b", &["main@origin"]);
+    create_commit(&local_dir, "c", &["release@origin"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b|c",
+        "--auto-branch",
+        "--keep-going",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 2 heads to push to Gerrit (remote 'origin'), target branches 'release', 'main'
+    Pushing to 'release': yostqsxw 16f1e6b4 c | c
+    Pushing to 'main': yqosqzyt dae20482 b | b
+    remote: error: hook declined to update refs/for/release
+    Warning: The remote rejected the following updates:
+      refs/for/release (reason: hook declined)
+    Hint: Try checking if you have permission to push to all the bookmarks.
+    Uploaded heads:
+      to 'main': yqosqzyt dae20482 b | b
+    Failed heads:
+      to 'release': yostqsxw 16f1e6b4 c | c
+    Error: Failed to push 1 of 2 heads to gerrit
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_record_change_number() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--record-change-number",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 9590bf26 c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:   https://gerrit.example.com/c/repo/+/2 c [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    Recorded Gerrit change numbers in 2 revisions
+    Working copy  (@) now at: yqosqzyt c0c6bf3f c | c
+    Parent commit (@-)      : mzvwutvl 191c29e4 b | b
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "b|c", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    @  c
+    │
+    │  Gerrit-Change: 2
+    ○  b
+    │
+    ~  Gerrit-Change: 1
+    [EOF]
+    ");
+
+    // Uploading again doesn't rewrite the revisions
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c",
+        "--remote-branch=main",
+        "--record-change-number",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt c0c6bf3f c | c
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b
+    remote:   https://gerrit.example.com/c/repo/+/2 c
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["log", "-r", "b|c", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    @  c
+    │
+    │  Gerrit-Change: 2
+    ○  b
+    │
+    ~  Gerrit-Change: 1
+    [EOF]
+    ");
+
+    // Changes with the same subject can't be told apart
+    create_commit(&local_dir, "d", &["c"]);
+    create_commit(&local_dir, "e", &["d"]);
+    local_dir
+        .run_jj(["describe", "d", "e", "-m", "same"])
+        .success();
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "e",
+        "--remote-branch=main",
+        "--record-change-number",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing kxryzmor 3fcd4583 e | same
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b
+    remote:   https://gerrit.example.com/c/repo/+/2 c
+    remote:   https://gerrit.example.com/c/repo/+/3 same [NEW]
+    remote:   https://gerrit.example.com/c/repo/+/4 same [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+      https://gerrit.example.com/c/repo/+/2
+      https://gerrit.example.com/c/repo/+/3
+      https://gerrit.example.com/c/repo/+/4
+    Warning: Could not determine the Gerrit change number of revision kxryzmorwvtz
+    Warning: Could not determine the Gerrit change number of revision wqnwkozpkust
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_remote_branch_with_slash() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) =
+        set_up_remote_and_clone_with(&test_env, &["a", "release/2.0"], &[]);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["release/2.0@origin"]);
+
+    // Explicit branch
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=release/1.0"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'release/1.0'
+    Pushing yqosqzyt 4d3f0881 b | b
+    [EOF]
+    ");
+
+    // Configured branch
+    test_env.add_config("gerrit.default-remote-branch = 'feature/x'");
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'feature/x'
+    Pushing yqosqzyt 4d3f0881 b | b
+    [EOF]
+    ");
+
+    // Inferred branch
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--auto-branch"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'release/2.0'
+    Pushing yostqsxw 4a8eef56 c | c
+    [EOF]
+    ");
+
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname)",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/feature/x
+    refs/for/release/1.0
+    refs/for/release/2.0
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_does_not_move_branch() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone_with(&test_env, &["a", "main"], &[]);
+    // Not a descendant of main, so a plain push to main would be a force push
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // Magic refs are refused as target branches
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote-branch=refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Target branch 'refs/for/main' is already a Gerrit magic ref
+    Hint: Pass the name of the branch to review against, e.g. `main`. Changes are always uploaded to `refs/for/<branch>`
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // So are branches which would add push options to the magic ref, whether
+    // they're passed on the command line or configured
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main%submit"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Target branch 'main%submit' contains '%'
+    Hint: Gerrit would read the part after '%' as push options. Use the upload flags, e.g. `--topic` or `--reviewer`, to set push options
+    [EOF]
+    [exit status: 1]
+    ");
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--config=gerrit.default-remote-branch='main%submit'",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Target branch 'main%submit' contains '%'
+    Hint: Gerrit would read the part after '%' as push options. Use the upload flags, e.g. `--topic` or `--reviewer`, to set push options
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yqosqzyt 4d3f0881 b | b
+    [EOF]
+    ");
+
+    // The branch on the remote is left alone
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "for-each-ref",
+        "--format=%(refname) %(subject)",
+        "refs/heads/main",
+        "refs/for",
+    ]);
+    insta::assert_snapshot!(output, @"
+    refs/for/main b
+    refs/heads/main main
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_custom_change_id_trailer_key() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir
+        .run_jj([
+            "describe",
+            "c",
+            "-m",
+            "c\n\nGerrit-Change-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9\n",
+        ])
+        .success();
+
+    test_env.add_config("gerrit.change-id-trailer-key = 'Gerrit-Change-Id'");
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%B",
+        "-2",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    c
+
+    Gerrit-Change-Id: Id39b308212fe7e0b746d16c13355f3a90712d7f9
+
+    b
+
+    Gerrit-Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+
+    [EOF]
+    ");
+
+    // Multiple trailers with the custom key are rejected
+    local_dir
+        .run_jj([
+            "describe",
+            "b",
+            "-m",
+            "b\n\nGerrit-Change-Id: I1\nGerrit-Change-Id: I2\n",
+        ])
+        .success();
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=other"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Multiple Gerrit-Change-Id footers in revision mzvwutvlkqwt
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_custom_change_id_trailer_key_fake_gerrit() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    gerrit.set_change_id_key("Gerrit-Change-Id");
+    let _guard = sideband_insta_settings().bind_to_scope();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    test_env.add_config("gerrit.change-id-trailer-key = 'Gerrit-Change-Id'");
+
+    // The server finds the Change-Id under the custom key
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    remote:
+    remote: SUCCESS
+    remote:
+    remote:   https://gerrit.example.com/c/repo/+/1 b [NEW]
+    remote:
+    View change at:
+      https://gerrit.example.com/c/repo/+/1
+    [EOF]
+    ");
+
+    // The revset function and `jj gerrit status` agree on the key
+    local_dir
+        .run_jj([
+            "describe",
+            "b",
+            "-m",
+            "b\n\nGerrit-Change-Id: I1111111111111111111111111111111111111111\n",
+        ])
+        .success();
+    let output = local_dir.run_jj(["log", "-r", "gerrit_uploaded()", "-T", "description"]);
+    insta::assert_snapshot!(output, @"
+    @  b
+    │
+    ~  Gerrit-Change-Id: I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+    let output = local_dir.run_jj(["gerrit", "status", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    24b64ef63e8d valid    I1111111111111111111111111111111111111111
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_skip() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "local-only", &["b"]);
+    test_env.add_config(r#"gerrit.skip = "description(glob:'local-only*')""#);
+
+    // The skipped head isn't uploaded, but its parent is
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "local-only",
+        "--remote-branch=main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Skipping 1 revisions matching `gerrit.skip`:
+      yqosqzyt d1daacc3 local-only | local-only
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+
+    // Nothing left to upload
+    create_commit(&local_dir, "c", &["a@origin"]);
+    local_dir
+        .run_jj(["describe", "c", "-m", "local-only c"])
+        .success();
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Skipping 1 revisions matching `gerrit.skip`:
+      znkkpsqq d1d9d210 c | local-only c
+    No revisions to upload.
+    [EOF]
+    ");
+
+    // Skipped revisions can't be in the middle of a stack
+    create_commit(&local_dir, "d", &["local-only"]);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "d", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Cannot skip revision yqosqzytrlsw because revision lylxulplsnyw is based on it
+    Hint: Revisions matching `gerrit.skip` must not have descendants among the revisions to upload
+    [EOF]
+    [exit status: 1]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_default_revision() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    test_env.add_config("gerrit.default-remote-branch = 'main'");
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir.run_jj(["new", "c"]).success();
+
+    // The empty working-copy commit is rejected like with `-r @`
+    let output = local_dir.run_jj(["gerrit", "upload"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Refusing to upload revision yostqsxwqrlt because it is empty
+    Hint: Perhaps you squashed then ran upload? Maybe you meant to upload the parent commit instead (eg. @-), or pass `--allow-empty` to upload it anyway
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // Without `-r`, `@` is uploaded along with its mutable ancestors
+    local_dir.run_jj(["describe", "-m", "d"]).success();
+    local_dir.write_file("d", "d\n");
+    let output = local_dir.run_jj(["gerrit", "upload"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yostqsxw d730d741 d
+    [EOF]
+    ");
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%s",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    d
+    c
+    b
+    a
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_stack() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    create_commit(&local_dir, "d", &["c"]);
+    create_commit(&local_dir, "other", &["a@origin"]);
+    local_dir.run_jj(["edit", "c"]).success();
+
+    let output = local_dir.run_jj(["gerrit", "upload", "--stack", "--remote-branch=main"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Pushing yostqsxw d078b269 d | d
+    [EOF]
+    ");
+
+    // Large stacks require confirmation
+    test_env.add_config("gerrit.upload.confirm-threshold = 2");
+    let output = local_dir.run_jj(["gerrit", "upload", "--stack", "--remote-branch=other"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Warning: `--stack` selected 3 revisions to upload
+    Error: Refusing to upload 3 revisions without confirmation
+    Hint: Pass `--yes` to upload without confirmation
+    [EOF]
+    [exit status: 1]
+    ");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "--stack", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: the argument '--stack' cannot be used with '--revisions <REVISIONS>'
+
+    Usage: jj gerrit upload --stack
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_config_env_expansion() {
+    let mut test_env = TestEnvironment::default();
+    set_up_remote_and_clone(&test_env);
+    create_commit(&test_env.work_dir("local"), "b", &["a@origin"]);
+    test_env.add_config(indoc::indoc! {r#"
+        gerrit.default-remote = "${GERRIT_REMOTE}"
+        gerrit.default-remote-branch = "release/${GERRIT_BRANCH}"
+    "#});
+
+    // Unset variables are reported
+    let output = test_env
+        .work_dir("local")
+        .run_jj(["gerrit", "upload", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Environment variable `GERRIT_REMOTE` referenced by `gerrit.default-remote` is not set
+    [EOF]
+    [exit status: 1]
+    ");
+
+    test_env.add_env_var("GERRIT_REMOTE", "origin");
+    let output = test_env
+        .work_dir("local")
+        .run_jj(["gerrit", "upload", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: Environment variable `GERRIT_BRANCH` referenced by `gerrit.default-remote-branch` is not set
+    [EOF]
+    [exit status: 1]
+    ");
+
+    test_env.add_env_var("GERRIT_BRANCH", "2.0");
+    let output = test_env
+        .work_dir("local")
+        .run_jj(["gerrit", "upload", "-r", "b"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'release/2.0'
+    Pushing mzvwutvl 3bcb28c4 b | b
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_assume_default_branch() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["a@origin"]);
+
+    // An empty value doesn't change anything
+    test_env.add_config(r#"gerrit.assume-default-branch = """#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Error: No target branch specified via --remote-branch, and no 'gerrit.default-remote-branch' was found
+    [EOF]
+    [exit status: 1]
+    ");
+
+    // The note is only shown once for multiple heads
+    test_env.add_config(r#"gerrit.assume-default-branch = "main""#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b|c", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Hint: Assuming target branch 'main' from `gerrit.assume-default-branch`. Set `gerrit.default-remote-branch` or pass `--remote-branch` to choose another one.
+    Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+    Dry-run: Would push yqosqzyt cfd7d90d c | c
+    c | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Only used as a fallback
+    test_env.add_config(r#"gerrit.default-remote-branch = "other""#);
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--dry-run"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'other'
+    Dry-run: Would push mzvwutvl 3bcb28c4 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_remote_scoped_config() {
+    let test_env = TestEnvironment::default();
+    let (_remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    test_env
+        .run_jj_in(".", ["git", "init", "--colocate", "other"])
+        .success();
+    local_dir
+        .run_jj(["git", "remote", "add", "other", "../other"])
+        .success();
+    create_commit(&local_dir, "b", &["a@origin"]);
+    test_env.add_config(indoc::indoc! {"
+        gerrit.default-remote-branch = 'global'
+        gerrit.remotes.origin.default-remote-branch = 'origin-branch'
+    "});
+
+    // The remote-specific value overrides the global one
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote=origin", "-n"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'origin-branch'
+    Dry-run: Would push yqosqzyt 4d3f0881 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Remotes without specific values use the global one
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "b", "--remote=other", "-n"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'other'), target branch 'global'
+    Dry-run: Would push yqosqzyt 4d3f0881 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+
+    // Command-line arguments override both
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "b",
+        "--remote=origin",
+        "--remote-branch=cli",
+        "-n",
+    ]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    Found 1 heads to push to Gerrit (remote 'origin'), target branch 'cli'
+    Dry-run: Would push yqosqzyt 4d3f0881 b | b
+    b | 1 +
+    1 file changed, 1 insertion(+), 0 deletions(-)
+    [EOF]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_amend_message() {
+    let test_env = TestEnvironment::default();
+    let (gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    local_dir
+        .run_jj(["gerrit", "upload", "-r", "b", "--remote-branch=main"])
+        .success();
+
+    // Fix a typo in the description only
+    local_dir
+        .run_jj(["describe", "b", "-m", "b with fixed typo"])
+        .success();
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "--remote-branch=main",
+            "--amend-message",
+        ])
+        .success();
+    insta::assert_snapshot!(gerrit.pushes(), @"
+    81b723522d1c1a583a045eab5bfb323e45e6198d main
+    1a9b3bbc1f785eb166207f7a0af852717282f3d9 main m=Message_update
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_proxy() {
+    let test_env = TestEnvironment::default();
+    test_env.run_jj_in(".", ["git", "init", "repo"]).success();
+    let work_dir = test_env.work_dir("repo");
+    work_dir
+        .run_jj([
+            "git",
+            "remote",
+            "add",
+            "gerrit",
+            "http://gerrit.example.invalid/repo",
+        ])
+        .success();
+    create_commit(&work_dir, "a", &[]);
+
+    // Nothing listens on the proxy port, so the connection must fail there
+    // rather than while resolving the remote host.
+    test_env.add_config("gerrit.proxy = 'http://127.0.0.1:1'");
+    let output = work_dir.run_jj(["gerrit", "upload", "-r", "a", "--remote-branch=main"]);
+    let stderr = output.stderr.normalized();
+    assert!(stderr.contains("127.0.0.1"), "{stderr}");
+    assert!(
+        stderr.contains("Error: Failed to connect to the Gerrit remote"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Hint: Check your network connection and proxy settings"));
+}
+
+#[test]
+fn test_gerrit_upload_show_diff_stat() {
+    let test_env = TestEnvironment::default();
+    let (_gerrit, local_dir) = set_up_fake_gerrit(&test_env);
+    create_commit_with_files(&local_dir, "b", &["a@origin"], &[("file", "1\n2\n")]);
+    create_commit_with_files(&local_dir, "c", &["b"], &[("file", "1\n3\n")]);
+    create_commit(&local_dir, "d", &["a@origin"]);
+
+    // Each stack is relative to its base
+    let output = local_dir.run_jj([
+        "gerrit",
+        "upload",
+        "-r",
+        "c|d",
+        "--remote-branch=main",
+        "--show-diff-stat",
+    ]);
+    sideband_insta_settings().bind(|| {
+        insta::assert_snapshot!(output, @"
+        ------- stderr -------
+        Found 2 heads to push to Gerrit (remote 'origin'), target branch 'main'
+        Pushing yostqsxw 674e3ea3 d | d
+        d | 1 +
+        1 file changed, 1 insertion(+), 0 deletions(-)
+        remote:
+        remote: SUCCESS
+        remote:
+        remote:   https://gerrit.example.com/c/repo/+/1 d [NEW]
+        remote:
+        Pushing yqosqzyt bb0f5019 c | c
+        file | 2 ++
+        1 file changed, 2 insertions(+), 0 deletions(-)
+        remote:
+        remote: SUCCESS
+        remote:
+        remote:   https://gerrit.example.com/c/repo/+/2 b [NEW]
+        remote:   https://gerrit.example.com/c/repo/+/3 c [NEW]
+        remote:
+        View change at:
+          https://gerrit.example.com/c/repo/+/1
+          https://gerrit.example.com/c/repo/+/2
+          https://gerrit.example.com/c/repo/+/3
+        [EOF]
+        ");
+    });
+}
+
+#[test]
+fn test_gerrit_upload_trailer() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+    create_commit(&local_dir, "c", &["b"]);
+    local_dir
+        .run_jj(["describe", "c", "-m", "c\n\nBug: 1234\n"])
+        .success();
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "c",
+            "--remote-branch=main",
+            "--trailer",
+            "Bug=1234",
+            "--trailer=Tested-by=CI",
+        ])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%B",
+        "-2",
+        "refs/for/main",
+    ]);
+    insta::assert_snapshot!(output, @"
+    c
+
+    Bug: 1234
+    Tested-by: CI
+    Change-Id: I19b790168e73f7a73a98deae21e807c06a6a6964
+
+    b
+
+    Bug: 1234
+    Tested-by: CI
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+
+    [EOF]
+    ");
+
+    // New trailers go before an existing Change-Id, which must stay last
+    local_dir
+        .run_jj([
+            "describe",
+            "b",
+            "-m",
+            "b\n\nChange-Id: I1111111111111111111111111111111111111111\n",
+        ])
+        .success();
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "--remote-branch=other",
+            "--trailer=Tested-by=CI",
+        ])
+        .success();
+    let output = remote_dir.run_jj([
+        "util",
+        "exec",
+        "--",
+        "git",
+        "log",
+        "--format=%B",
+        "-1",
+        "refs/for/other",
+    ]);
+    insta::assert_snapshot!(output, @"
+    b
+
+    Tested-by: CI
+    Change-Id: I1111111111111111111111111111111111111111
+
+    [EOF]
+    ");
+
+    let output = local_dir.run_jj(["gerrit", "upload", "-r", "c", "--trailer", "Bug"]);
+    insta::assert_snapshot!(output, @"
+    ------- stderr -------
+    error: invalid value 'Bug' for '--trailer <KEY=VALUE>': Expected KEY=VALUE, got 'Bug'
+
+    For more information, try '--help'.
+    [EOF]
+    [exit status: 2]
+    ");
+}
+
+#[test]
+fn test_gerrit_upload_large_description() {
+    let test_env = TestEnvironment::default();
+    let (remote_dir, local_dir) = set_up_remote_and_clone(&test_env);
+    create_commit(&local_dir, "b", &["a@origin"]);
+
+    // A description of several megabytes with a large trailer paragraph.
+    // Processing it must stay linear in its size for this to finish quickly.
+    let mut description = "b\n\n".to_owned();
+    for i in 0..100_000 {
+        writeln!(description, "Line {i} of a very long description body.").unwrap();
+    }
+    description.push('\n');
+    for i in 0..10_000 {
+        writeln!(description, "Reviewed-by: Reviewer {i} <r{i}@example.com>").unwrap();
+    }
+    local_dir
+        .run_jj_with(|cmd| {
+            cmd.args(["describe", "b", "--stdin"])
+                .write_stdin(description.clone())
+        })
+        .success();
+
+    local_dir
+        .run_jj([
+            "gerrit",
+            "upload",
+            "-r",
+            "b",
+            "--remote-branch=main",
+            "--trailer=Bug=1234",
+        ])
+        .success();
+    let output = remote_dir
+        .run_jj([
+            "util",
+            "exec",
+            "--",
+            "git",
+            "log",
+            "-1",
+            "--format=%B",
+            "refs/for/main",
+        ])
+        .success();
+    let message = output.stdout.into_raw();
+    assert!(message.starts_with(&description));
+    let lines = message.trim_end().lines().collect_vec();
+    insta::assert_snapshot!(lines[lines.len() - 3..].join("\n"), @"
+    Reviewed-by: Reviewer 9999 <r9999@example.com>
+    Bug: 1234
+    Change-Id: Id043564ef93650b06a70f92f9d91912b6a6a6964
+    ");
 }
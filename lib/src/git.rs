@@ -43,6 +43,7 @@ use crate::config::ConfigGetError;
 use crate::file_util::IoResultExt as _;
 use crate::file_util::PathError;
 use crate::git_backend::GitBackend;
+use crate::git_subprocess::GitRemoteTarget;
 use crate::git_subprocess::GitSubprocessContext;
 use crate::git_subprocess::GitSubprocessError;
 use crate::index::IndexError;
@@ -80,6 +81,10 @@ pub const REMOTE_NAME_FOR_LOCAL_GIT_REPO: &RemoteName = RemoteName::new("git");
 pub const RESERVED_REMOTE_REF_NAMESPACE: &str = "refs/remotes/git/";
 /// Ref name used as a placeholder to unset HEAD without a commit.
 const UNBORN_ROOT_REF_NAME: &str = "refs/jj/root";
+/// Ref namespace where [`fetch_refs()`] temporarily stores the fetched refs.
+/// Each fetch uses its own randomly named sub-namespace, so concurrent fetches
+/// don't see each other's refs.
+const FETCHED_REF_NAMESPACE: &str = "refs/jj/fetched/";
 /// Dummy file to be added to the index to indicate that the user is editing a
 /// commit with a conflict that isn't represented in the Git index.
 const INDEX_DUMMY_CONFLICT_FILE: &str = ".jj-do-not-resolve-this-conflict";
@@ -2311,6 +2316,16 @@ pub enum GitFetchError {
     RemoteName(#[from] GitRemoteNameError),
     #[error(transparent)]
     Subprocess(#[from] GitSubprocessError),
+    #[error("Failed to read the fetched refs")]
+    ReadFetchedRefs(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("Failed to remove the fetched refs")]
+    RemoveFetchedRefs(#[source] Box<gix::reference::edit::Error>),
+    #[error("Failed to import the fetched commits")]
+    ImportFetchedCommits(#[source] BackendError),
+    #[error("Unexpected failing refspec: {0}")]
+    UnexpectedFailingRefspec(String),
+    #[error(transparent)]
+    UnexpectedBackend(#[from] UnexpectedGitBackendError),
 }
 
 #[derive(Error, Debug)]
@@ -2685,7 +2700,7 @@ impl<'a> GitFetch<'a> {
         // even more unfortunately, git errors out one refspec at a time,
         // meaning that the below cycle runs in O(#failed refspecs)
         while let Some(failing_refspec) = self.git_ctx.spawn_fetch(
-            remote_name,
+            GitRemoteTarget::Remote(remote_name),
             &remaining_refspecs,
             &negative_refspecs,
             &mut callbacks,
@@ -2762,6 +2777,145 @@ impl<'a> GitFetch<'a> {
     }
 }
 
+/// Fetches the Git refs matching the `patterns` without updating the repo
+/// view.
+///
+/// The patterns are fully-qualified ref names, which may contain a `*` glob
+/// like in refspecs. Returns the fetched refs along with the commits they point
+/// to; refs that don't exist on the remote are omitted. The fetched commits are
+/// imported into the backend, which protects them from garbage collection, but
+/// they aren't added to the view.
+pub fn fetch_refs(
+    repo: &dyn Repo,
+    subprocess_options: GitSubprocessOptions,
+    remote_name: &RemoteName,
+    patterns: &[&str],
+    callbacks: RemoteCallbacks,
+) -> Result<Vec<(String, CommitId)>, GitFetchError> {
+    let git_backend = get_git_backend(repo.store())?;
+    // check the remote exists
+    if git_backend
+        .git_repo()
+        .try_find_remote(remote_name.as_str())
+        .is_none()
+    {
+        return Err(GitFetchError::NoSuchRemote(remote_name.to_owned()));
+    }
+    fetch_refs_inner(
+        git_backend,
+        subprocess_options,
+        GitRemoteTarget::Remote(remote_name),
+        patterns,
+        callbacks,
+    )
+}
+
+/// Like [`fetch_refs()`], but fetches from the repository at `url` instead of
+/// a configured remote.
+///
+/// The URL is passed to Git as is, so `url.<base>.insteadOf` rewrites apply to
+/// it.
+pub fn fetch_refs_from_url(
+    repo: &dyn Repo,
+    subprocess_options: GitSubprocessOptions,
+    url: &str,
+    patterns: &[&str],
+    callbacks: RemoteCallbacks,
+) -> Result<Vec<(String, CommitId)>, GitFetchError> {
+    let git_backend = get_git_backend(repo.store())?;
+    fetch_refs_inner(
+        git_backend,
+        subprocess_options,
+        GitRemoteTarget::Url(url),
+        patterns,
+        callbacks,
+    )
+}
+
+fn fetch_refs_inner(
+    git_backend: &GitBackend,
+    subprocess_options: GitSubprocessOptions,
+    remote: GitRemoteTarget<'_>,
+    patterns: &[&str],
+    mut callbacks: RemoteCallbacks,
+) -> Result<Vec<(String, CommitId)>, GitFetchError> {
+    let git_repo = git_backend.git_repo();
+    let git_ctx = GitSubprocessContext::from_git_backend(git_backend, subprocess_options);
+
+    // The refs are fetched into a private namespace, from which they're removed
+    // again once they've been looked up, or if the fetch fails.
+    let namespace = format!("{FETCHED_REF_NAMESPACE}{:016x}/", rand::random::<u64>());
+    let list_fetched_refs = || -> Result<Vec<gix::Reference<'_>>, GitFetchError> {
+        let platform = git_repo
+            .references()
+            .map_err(|err| GitFetchError::ReadFetchedRefs(err.into()))?;
+        platform
+            .prefixed(namespace.as_str())
+            .map_err(|err| GitFetchError::ReadFetchedRefs(err.into()))?
+            .map(|git_ref| git_ref.map_err(GitFetchError::ReadFetchedRefs))
+            .try_collect()
+    };
+    let remove_fetched_refs = |git_refs: Vec<gix::Reference<'_>>| {
+        git_repo
+            .edit_references(git_refs.into_iter().map(remove_ref))
+            .map_err(|err| GitFetchError::RemoveFetchedRefs(Box::new(err)))
+    };
+
+    let mut remaining_refspecs = patterns
+        .iter()
+        .unique()
+        .map(|&pattern| {
+            let name = pattern.strip_prefix("refs/").unwrap_or(pattern);
+            RefSpec::forced(pattern, format!("{namespace}{name}"))
+        })
+        .collect_vec();
+    let mut fetch = || -> Result<(), GitFetchError> {
+        // Like in `GitFetch::fetch()`, drop the refs missing on the remote one
+        // at a time until the fetch succeeds.
+        while let Some(failing_refspec) = git_ctx.spawn_fetch(
+            remote,
+            &remaining_refspecs,
+            &[],
+            &mut callbacks,
+            None,
+            Some(FetchTagsOverride::NoTags),
+        )? {
+            tracing::debug!(failing_refspec, "failed to fetch ref");
+            let count = remaining_refspecs.len();
+            remaining_refspecs.retain(|r| r.source.as_ref() != Some(&failing_refspec));
+            if remaining_refspecs.len() == count {
+                return Err(GitFetchError::UnexpectedFailingRefspec(failing_refspec));
+            }
+        }
+        Ok(())
+    };
+    if let Err(err) = fetch() {
+        // Git may have fetched some of the refs before failing. The fetch
+        // error is more useful than a failure to clean them up.
+        if let Err(cleanup_err) = list_fetched_refs().and_then(remove_fetched_refs) {
+            tracing::warn!(?cleanup_err, "failed to remove fetched refs");
+        }
+        return Err(err);
+    }
+
+    let git_refs = list_fetched_refs()?;
+    let fetched_refs = git_refs
+        .iter()
+        .filter_map(|git_ref| {
+            let name = git_ref.name().as_bstr().to_string();
+            let name = format!("refs/{}", &name[namespace.len()..]);
+            let oid = resolve_git_ref_to_commit_id(git_ref, None)?;
+            Some((name, CommitId::from_bytes(oid.as_bytes())))
+        })
+        .collect_vec();
+    // Keep the commits from being garbage collected once the refs are gone.
+    git_backend
+        .import_head_commits(fetched_refs.iter().map(|(_, id)| id))
+        .map_err(GitFetchError::ImportFetchedCommits)?;
+    remove_fetched_refs(git_refs)?;
+    Ok(fetched_refs)
+}
+
 #[derive(Error, Debug)]
 pub enum GitPushError {
     #[error("No git remote named '{}'", .0.as_symbol())]
@@ -2867,6 +3021,53 @@ pub fn push_updates(
     subprocess_options: GitSubprocessOptions,
     remote_name: &RemoteName,
     updates: &[GitRefUpdate],
+    callbacks: RemoteCallbacks,
+) -> Result<GitPushStats, GitPushError> {
+    let git_backend = get_git_backend(repo.store())?;
+    // check the remote exists
+    if git_backend
+        .git_repo()
+        .try_find_remote(remote_name.as_str())
+        .is_none()
+    {
+        return Err(GitPushError::NoSuchRemote(remote_name.to_owned()));
+    }
+    push_updates_inner(
+        git_backend,
+        subprocess_options,
+        GitRemoteTarget::Remote(remote_name),
+        updates,
+        callbacks,
+    )
+}
+
+/// Like [`push_updates()`], but pushes to the repository at `url` instead of a
+/// configured remote.
+///
+/// The URL is passed to Git as is, so `url.<base>.insteadOf` rewrites apply to
+/// it.
+pub fn push_updates_to_url(
+    repo: &dyn Repo,
+    subprocess_options: GitSubprocessOptions,
+    url: &str,
+    updates: &[GitRefUpdate],
+    callbacks: RemoteCallbacks,
+) -> Result<GitPushStats, GitPushError> {
+    let git_backend = get_git_backend(repo.store())?;
+    push_updates_inner(
+        git_backend,
+        subprocess_options,
+        GitRemoteTarget::Url(url),
+        updates,
+        callbacks,
+    )
+}
+
+fn push_updates_inner(
+    git_backend: &GitBackend,
+    subprocess_options: GitSubprocessOptions,
+    remote: GitRemoteTarget<'_>,
+    updates: &[GitRefUpdate],
     mut callbacks: RemoteCallbacks,
 ) -> Result<GitPushStats, GitPushError> {
     let mut qualified_remote_refs_expected_locations = HashMap::new();
@@ -2889,21 +3090,14 @@ pub fn push_updates(
         }
     }
 
-    let git_backend = get_git_backend(repo.store())?;
-    let git_repo = git_backend.git_repo();
     let git_ctx = GitSubprocessContext::from_git_backend(git_backend, subprocess_options);
 
-    // check the remote exists
-    if git_repo.try_find_remote(remote_name.as_str()).is_none() {
-        return Err(GitPushError::NoSuchRemote(remote_name.to_owned()));
-    }
-
     let refs_to_push: Vec<RefToPush> = refspecs
         .iter()
         .map(|full_refspec| RefToPush::new(full_refspec, &qualified_remote_refs_expected_locations))
         .collect();
 
-    let mut push_stats = git_ctx.spawn_push(remote_name, &refs_to_push, &mut callbacks)?;
+    let mut push_stats = git_ctx.spawn_push(remote, &refs_to_push, &mut callbacks)?;
     push_stats.pushed.sort();
     push_stats.rejected.sort();
     push_stats.remote_rejected.sort();
@@ -76,6 +76,25 @@ pub enum GitSubprocessError {
     External(String),
 }
 
+/// Repository a Git subprocess fetches from or pushes to
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum GitRemoteTarget<'a> {
+    /// A remote configured in the local repository
+    Remote(&'a RemoteName),
+    /// A URL, which Git may rewrite according to `url.<base>.insteadOf`
+    Url(&'a str),
+}
+
+impl GitRemoteTarget<'_> {
+    /// The argument identifying the repository on the Git command line
+    fn as_arg(&self) -> &str {
+        match self {
+            Self::Remote(remote_name) => remote_name.as_str(),
+            Self::Url(url) => url,
+        }
+    }
+}
+
 /// Context for creating Git subprocesses
 pub(crate) struct GitSubprocessContext {
     git_dir: PathBuf,
@@ -166,7 +185,7 @@ impl GitSubprocessContext {
     /// Note that git only returns one failed ref at a time
     pub(crate) fn spawn_fetch(
         &self,
-        remote_name: &RemoteName,
+        remote: GitRemoteTarget<'_>,
         refspecs: &[RefSpec],
         negative_refspecs: &[NegativeRefSpec],
         callbacks: &mut RemoteCallbacks<'_>,
@@ -196,7 +215,7 @@ impl GitSubprocessContext {
             }
             None => {}
         }
-        command.arg("--").arg(remote_name.as_str());
+        command.arg("--").arg(remote.as_arg());
         command.args(
             refspecs
                 .iter()
@@ -263,7 +282,7 @@ impl GitSubprocessContext {
     ///     2. refs that succeeded to push
     pub(crate) fn spawn_push(
         &self,
-        remote_name: &RemoteName,
+        remote: GitRemoteTarget<'_>,
         references: &[RefToPush],
         callbacks: &mut RemoteCallbacks<'_>,
     ) -> Result<GitPushStats, GitSubprocessError> {
@@ -283,7 +302,7 @@ impl GitSubprocessContext {
                 .iter()
                 .map(|reference| format!("--force-with-lease={}", reference.to_git_lease())),
         );
-        command.args(["--", remote_name.as_str()]);
+        command.args(["--", remote.as_arg()]);
         // with --force-with-lease we cannot have the forced refspec,
         // as it ignores the lease
         command.args(
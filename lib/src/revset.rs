@@ -760,6 +760,18 @@ pub type RevsetFunction = fn(
     &LoweringContext,
 ) -> Result<Arc<UserRevsetExpression>, RevsetParseError>;
 
+/// Revset function registered by an extension. Unlike [`RevsetFunction`], it
+/// may capture state, such as values read from the settings.
+pub type RevsetCustomFunction = Arc<
+    dyn Fn(
+            &mut RevsetDiagnostics,
+            &FunctionCallNode,
+            &LoweringContext,
+        ) -> Result<Arc<UserRevsetExpression>, RevsetParseError>
+        + Send
+        + Sync,
+>;
+
 static BUILTIN_FUNCTION_MAP: LazyLock<HashMap<&str, RevsetFunction>> = LazyLock::new(|| {
     // Not using maplit::hashmap!{} or custom declarative macro here because
     // code completion inside macro is quite restricted.
@@ -3395,7 +3407,7 @@ impl<I: Iterator<Item = Result<CommitId, RevsetEvaluationError>>> Iterator
 /// A set of extensions for revset evaluation.
 pub struct RevsetExtensions {
     symbol_resolvers: Vec<Box<dyn SymbolResolverExtension>>,
-    function_map: HashMap<&'static str, RevsetFunction>,
+    function_map: HashMap<&'static str, RevsetCustomFunction>,
 }
 
 impl Default for RevsetExtensions {
@@ -3408,7 +3420,10 @@ impl RevsetExtensions {
     pub fn new() -> Self {
         Self {
             symbol_resolvers: vec![],
-            function_map: BUILTIN_FUNCTION_MAP.clone(),
+            function_map: BUILTIN_FUNCTION_MAP
+                .iter()
+                .map(|(&name, &func)| (name, Arc::new(func) as RevsetCustomFunction))
+                .collect(),
         }
     }
 
@@ -3421,6 +3436,12 @@ impl RevsetExtensions {
     }
 
     pub fn add_custom_function(&mut self, name: &'static str, func: RevsetFunction) {
+        self.add_custom_closure(name, Arc::new(func));
+    }
+
+    /// Registers a revset function which may capture state, such as values
+    /// read from the settings.
+    pub fn add_custom_closure(&mut self, name: &'static str, func: RevsetCustomFunction) {
         match self.function_map.entry(name) {
             hash_map::Entry::Occupied(_) => {
                 panic!("Conflict registering revset function '{name}'")
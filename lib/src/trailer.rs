@@ -14,6 +14,8 @@
 
 //! Parsing trailers from commit messages.
 
+use std::sync::LazyLock;
+
 use itertools::Itertools as _;
 use thiserror::Error;
 
@@ -93,8 +95,9 @@ fn parse_trailers_impl(body: &str) -> (Vec<Trailer>, bool, bool, Option<String>)
     // to ensure we parse the trailer in an unambiguous manner; this avoids cases
     // where a colon in the body of the message is mistaken for a trailer
     let lines = body.trim_ascii_end().lines().rev();
-    let trailer_re =
-        regex::Regex::new(r"^([a-zA-Z0-9-]+) *: *(.*)$").expect("Trailer regex should be valid");
+    static TRAILER_RE: LazyLock<regex::Regex> = LazyLock::new(|| {
+        regex::Regex::new(r"^([a-zA-Z0-9-]+) *: *(.*)$").expect("Trailer regex should be valid")
+    });
     let mut trailers: Vec<Trailer> = Vec::new();
     let mut multiline_value = vec![];
     let mut found_blank = false;
@@ -103,7 +106,7 @@ fn parse_trailers_impl(body: &str) -> (Vec<Trailer>, bool, bool, Option<String>)
     for line in lines {
         if line.starts_with(' ') {
             multiline_value.push(line);
-        } else if let Some(groups) = trailer_re.captures(line) {
+        } else if let Some(groups) = TRAILER_RE.captures(line) {
             let key = groups[1].to_string();
             multiline_value.push(groups.get(2).unwrap().as_str());
             // trim the end of the multiline value
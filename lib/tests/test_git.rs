@@ -3965,6 +3965,186 @@ fn test_fetch_with_fetch_tags_override() {
     assert_eq!(changed_tags(&stats), expected_changed_tags);
 }
 
+#[test]
+fn test_fetch_refs() {
+    let test_data = GitRepoData::create();
+    let subprocess_options =
+        GitSubprocessOptions::from_settings(test_data.repo.settings()).unwrap();
+    let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    let change1_git_commit = empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/01/1/1",
+        &[initial_git_commit],
+    );
+    let change2_git_commit1 = empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/02/2/1",
+        &[initial_git_commit],
+    );
+    let change2_git_commit2 = empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/02/2/2",
+        &[initial_git_commit],
+    );
+
+    // Exact names and globs can be mixed, and missing refs are omitted
+    let fetched_refs = git::fetch_refs(
+        test_data.repo.as_ref(),
+        subprocess_options,
+        "origin".as_ref(),
+        &[
+            "refs/changes/01/1/1",
+            "refs/changes/02/2/*",
+            "refs/changes/03/3/1",
+        ],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        fetched_refs,
+        vec![
+            ("refs/changes/01/1/1".to_owned(), jj_id(change1_git_commit)),
+            ("refs/changes/02/2/1".to_owned(), jj_id(change2_git_commit1)),
+            ("refs/changes/02/2/2".to_owned(), jj_id(change2_git_commit2)),
+        ]
+    );
+
+    // Only the keep refs protecting the fetched commits from GC are left
+    // behind in the local Git repo
+    let git_ref_names = test_data
+        .git_repo
+        .references()
+        .unwrap()
+        .all()
+        .unwrap()
+        .map(|git_ref| git_ref.unwrap().name().as_bstr().to_string())
+        .sorted()
+        .collect_vec();
+    let expected_ref_names = [change1_git_commit, change2_git_commit1, change2_git_commit2]
+        .iter()
+        .map(|id| format!("refs/jj/keep/{id}"))
+        .sorted()
+        .collect_vec();
+    assert_eq!(git_ref_names, expected_ref_names);
+
+    // The fetched commits and their ancestors can be read
+    let commit = test_data
+        .repo
+        .store()
+        .get_commit(&jj_id(change1_git_commit))
+        .unwrap();
+    assert_eq!(commit.parent_ids(), [jj_id(initial_git_commit)]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_fetch_refs_failure_removes_fetched_refs() {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let test_data = GitRepoData::create();
+    let subprocess_options =
+        GitSubprocessOptions::from_settings(test_data.repo.settings()).unwrap();
+    let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/01/1/1",
+        &[initial_git_commit],
+    );
+    empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/02/2/1",
+        &[initial_git_commit],
+    );
+
+    // Make Git fail after it has written some of the fetched refs
+    let hook_path = test_data
+        .git_repo
+        .path()
+        .join("hooks/reference-transaction");
+    fs::create_dir_all(hook_path.parent().unwrap()).unwrap();
+    fs::write(
+        &hook_path,
+        "#!/bin/sh\n[ \"$1\" = prepared ] && grep -q /02/2/1 && exit 1\nexit 0\n",
+    )
+    .unwrap();
+    fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let result = git::fetch_refs(
+        test_data.repo.as_ref(),
+        subprocess_options,
+        "origin".as_ref(),
+        &["refs/changes/01/1/1", "refs/changes/02/2/1"],
+        git::RemoteCallbacks::default(),
+    );
+    assert!(matches!(result, Err(GitFetchError::Subprocess(_))));
+
+    // No refs are left behind in the namespace the refs were fetched into
+    let git_ref_names = test_data
+        .git_repo
+        .references()
+        .unwrap()
+        .prefixed("refs/jj/")
+        .unwrap()
+        .map(|git_ref| git_ref.unwrap().name().as_bstr().to_string())
+        .collect_vec();
+    assert_eq!(git_ref_names, Vec::<String>::new());
+}
+
+#[test]
+fn test_fetch_refs_no_such_remote() {
+    let test_data = GitRepoData::create();
+    let subprocess_options =
+        GitSubprocessOptions::from_settings(test_data.repo.settings()).unwrap();
+    let result = git::fetch_refs(
+        test_data.repo.as_ref(),
+        subprocess_options,
+        "invalid-remote".as_ref(),
+        &["refs/changes/01/1/1"],
+        git::RemoteCallbacks::default(),
+    );
+    assert!(matches!(result, Err(GitFetchError::NoSuchRemote(_))));
+
+    // URLs must be passed to `fetch_refs_from_url()` instead
+    let url = format!("file://{}", test_data.origin_repo.path().display());
+    let subprocess_options =
+        GitSubprocessOptions::from_settings(test_data.repo.settings()).unwrap();
+    let result = git::fetch_refs(
+        test_data.repo.as_ref(),
+        subprocess_options,
+        url.as_ref(),
+        &["refs/changes/01/1/1"],
+        git::RemoteCallbacks::default(),
+    );
+    assert!(matches!(result, Err(GitFetchError::NoSuchRemote(_))));
+}
+
+#[test]
+fn test_fetch_refs_from_url() {
+    let test_data = GitRepoData::create();
+    let subprocess_options =
+        GitSubprocessOptions::from_settings(test_data.repo.settings()).unwrap();
+    let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    let change_git_commit = empty_git_commit(
+        &test_data.origin_repo,
+        "refs/changes/01/1/1",
+        &[initial_git_commit],
+    );
+
+    let url = format!("file://{}", test_data.origin_repo.path().display());
+    let fetched_refs = git::fetch_refs_from_url(
+        test_data.repo.as_ref(),
+        subprocess_options,
+        &url,
+        &["refs/changes/01/1/1"],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        fetched_refs,
+        vec![("refs/changes/01/1/1".to_owned(), jj_id(change_git_commit))]
+    );
+}
+
 struct PushTestSetup {
     source_repo_dir: PathBuf,
     jj_repo: Arc<ReadonlyRepo>,
@@ -4830,6 +5010,60 @@ fn test_push_updates_invalid_remote() {
     assert!(matches!(result, Err(GitPushError::NoSuchRemote(_))));
 }
 
+#[test]
+fn test_push_updates_to_missing_url() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let setup = set_up_push_repos(&settings, &temp_dir);
+    let subprocess_options = GitSubprocessOptions::from_settings(&settings).unwrap();
+    // URLs are passed to git, which fails to push to them
+    let url = format!(
+        "file://{}",
+        temp_dir.path().join("invalid-remote").display()
+    );
+    let result = git::push_updates_to_url(
+        setup.jj_repo.as_ref(),
+        subprocess_options,
+        &url,
+        &[GitRefUpdate {
+            qualified_name: "refs/heads/main".into(),
+            expected_current_target: Some(setup.main_commit.id().clone()),
+            new_target: Some(setup.child_of_main_commit.id().clone()),
+        }],
+        git::RemoteCallbacks::default(),
+    );
+    assert!(matches!(result, Err(GitPushError::Subprocess(_))));
+}
+
+#[test]
+fn test_push_updates_to_url() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let setup = set_up_push_repos(&settings, &temp_dir);
+    let subprocess_options = GitSubprocessOptions::from_settings(&settings).unwrap();
+    let url = format!("file://{}", setup.source_repo_dir.display());
+    let stats = git::push_updates_to_url(
+        setup.jj_repo.as_ref(),
+        subprocess_options,
+        &url,
+        &[GitRefUpdate {
+            qualified_name: "refs/for/main".into(),
+            expected_current_target: None,
+            new_target: Some(setup.child_of_main_commit.id().clone()),
+        }],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
+    assert!(stats.all_ok());
+
+    let source_repo = testutils::git::open(&setup.source_repo_dir);
+    let new_target = source_repo.find_reference("refs/for/main").unwrap();
+    assert_eq!(
+        new_target.target().id(),
+        git_id(&setup.child_of_main_commit)
+    );
+}
+
 #[test]
 fn test_push_environment_options() {
     let settings = testutils::user_settings();